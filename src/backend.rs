@@ -0,0 +1,174 @@
+use egui_inspect::logging::log::error;
+use egui_inspect::EguiInspect;
+use ndarray::{Array2, Array3};
+use std::path::PathBuf;
+
+use crate::buddhabrot::HistogramState;
+use crate::cpu_compute::CpuCompute;
+use crate::fractal_compute::FractalCompute;
+use crate::shape_trap::TrapOutput;
+use crate::wrapper_types::{
+    BBox, BuddhabrotParam, Complex, DistanceParams, Freqs, LyapunovParam, NewtonParam,
+    PaletteMapping, ProxType, SFParam, SceneParam, ShapeTrapParam, SmoothParams,
+};
+
+pub type BackendResult<T> = Result<T, String>;
+
+/// Where the field and colouring stages get executed
+#[derive(Default, EguiInspect, PartialEq, Clone, Copy, Debug)]
+pub enum BackendKind {
+    #[default]
+    OpenCL,
+    Cpu,
+}
+
+/// Operations shared by all compute backends, fields are indexed 1 to 3 as in the kernels
+pub trait RenderBackend: Send {
+    fn sampled_path(&self) -> Option<&PathBuf>;
+
+    fn has_sampled(&self) -> bool;
+
+    fn update_sampled(&mut self, ip: PathBuf);
+
+    /// Reallocates the fields, histograms and image for `im_dims`, the compiled program and
+    /// sampled image are kept
+    fn resize(&mut self, im_dims: (usize, usize));
+
+    fn run_escape_iter(&mut self, fi: usize, fparam: SFParam) -> BackendResult<()>;
+
+    /// Period of the cycle interior orbits fall into, periodicity detection forced on
+    fn run_interior_period(&mut self, fi: usize, fparam: SFParam) -> BackendResult<()>;
+
+    fn run_smooth_escape_iter(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        smooth: SmoothParams,
+    ) -> BackendResult<()>;
+
+    /// Escape iteration relative to a reference orbit, `fparam.view` being relative to the
+    /// orbit's starting point. Plain iteration count when no smoothing is given.
+    fn run_perturbed_escape_iter(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        reference: &[Complex],
+        smooth: Option<SmoothParams>,
+    ) -> BackendResult<()>;
+
+    /// Exterior distance estimate in pixels, scaled by the thickness and clamped to [0, 1]
+    fn run_distance_estimate(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        distance: DistanceParams,
+    ) -> BackendResult<()>;
+
+    fn run_min_prox(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        prox_type: ProxType,
+    ) -> BackendResult<()>;
+
+    fn run_box_trap_partial(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        box_: BBox,
+        real: bool,
+    ) -> BackendResult<()>;
+
+    /// Distance of the orbits to a union of trap shapes, or where they were trapped
+    fn run_shape_trap(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        trap: ShapeTrapParam,
+        output: TrapOutput,
+    ) -> BackendResult<()>;
+
+    /// Newton's method on a polynomial, either the root converged to (as (k+1)/degree, 0 when
+    /// not converged) or the iterations taken
+    fn run_newton(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        newton: NewtonParam,
+        root: bool,
+    ) -> BackendResult<()>;
+
+    /// Lyapunov exponent of the logistic map, squashed into [0, 1] with stable exponents above
+    /// 0.5, the view's [-2, 2] square covering the rates [0, 4]
+    fn run_lyapunov(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        lyapunov: LyapunovParam,
+    ) -> BackendResult<()>;
+
+    /// Splats a pass of buddhabrot orbits into the three histograms, clearing them first when
+    /// they were accumulated for other inputs
+    fn run_buddhabrot_pass(
+        &mut self,
+        fparam: SFParam,
+        buddha: BuddhabrotParam,
+    ) -> BackendResult<()>;
+
+    fn histogram_state(&self) -> &HistogramState;
+
+    /// Maps histogram k into field k as 1 - exp(-exposure * density), see
+    /// `HistogramState::density_scales`
+    fn run_map_histograms(&mut self, exposure: f64) -> BackendResult<()>;
+
+    /// Ray marches the 3D scene straight into the rgb image, pixels placed on the film through
+    /// the view of `fparam` within `scene.frame`
+    fn run_ray_march(&mut self, fparam: SFParam, scene: SceneParam) -> BackendResult<()>;
+
+    fn run_map_sines(&mut self, freqs: Freqs) -> BackendResult<()>;
+
+    /// Colours field 1 through a palette lookup table of shape (len, 3)
+    fn run_map_palette(&mut self, lut: &Array2<u8>, mapping: PaletteMapping) -> BackendResult<()>;
+
+    /// Colours the root basins of field 1 with a row of `colors` each, darkened by the
+    /// iterations in field 2 (as from `run_newton`)
+    fn run_map_basins(&mut self, colors: &Array2<u8>, shading: f64) -> BackendResult<()>;
+
+    fn run_pack(&mut self, normalise: bool) -> BackendResult<()>;
+
+    fn run_map_img(&mut self, bilinear: bool) -> BackendResult<()>;
+
+    /// Brings the coloured image back to the host
+    fn read_rgb(&mut self) -> BackendResult<()>;
+
+    /// Host copy of the coloured image, as of the last `read_rgb`
+    fn rgb(&self) -> &Array3<u8>;
+
+    /// Host copy of the coloured image, for post processing on the host
+    fn rgb_mut(&mut self) -> &mut Array3<u8>;
+}
+
+pub fn create_backend(
+    kind: BackendKind,
+    im_dims: (usize, usize),
+    custom_iter_func: Option<String>,
+) -> BackendResult<Box<dyn RenderBackend>> {
+    Ok(match kind {
+        BackendKind::OpenCL => Box::new(FractalCompute::new(im_dims, custom_iter_func)?),
+        BackendKind::Cpu => Box::new(CpuCompute::new(im_dims)),
+    })
+}
+
+/// As `create_backend`, but falls back to the CPU backend when the requested one is unavailable
+pub fn create_backend_or_cpu(
+    kind: BackendKind,
+    im_dims: (usize, usize),
+) -> (BackendKind, Box<dyn RenderBackend>) {
+    match create_backend(kind, im_dims, None) {
+        Ok(backend) => (kind, backend),
+        Err(err) => {
+            error!("Could not create {kind:?} backend, falling back to CPU: {err}");
+            (BackendKind::Cpu, Box::new(CpuCompute::new(im_dims)))
+        }
+    }
+}
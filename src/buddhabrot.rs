@@ -0,0 +1,107 @@
+//! Buddhabrot style density renders, orbits of random starting points are splatted into
+//! histograms of the view instead of each pixel following its own orbit. The histograms
+//! accumulate over passes for as long as their inputs stay the same.
+
+use egui_inspect::{EguiInspect, InspectNumber};
+use serde::{Deserialize, Serialize};
+
+use crate::wrapper_types::{BuddhabrotParam, SFParam};
+
+/// Side of the square around 0 starting points are drawn from
+pub static SAMPLE_SQUARE_SIDE: f64 = 4.0;
+
+/// UI for buddhabrot params, three iteration limits give a "nebulabrot" in rgb
+#[derive(Clone, PartialEq, EguiInspect, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BuddhabrotParamUI {
+    /// splat the orbits that stay bounded instead (anti-buddhabrot)
+    pub anti: bool,
+    #[inspect(log_slider, min = 1.0, max = 100000.0)]
+    pub max_iter_r: i32,
+    #[inspect(log_slider, min = 1.0, max = 100000.0)]
+    pub max_iter_g: i32,
+    #[inspect(log_slider, min = 1.0, max = 100000.0)]
+    pub max_iter_b: i32,
+    /// random starting points per pixel and pass
+    #[inspect(min = 1.0, max = 64.0)]
+    pub samples: i32,
+    /// passes accumulated before the render is complete
+    #[inspect(log_slider, min = 1.0, max = 10000.0)]
+    pub passes: i32,
+    #[inspect(log_slider, min = 1e-4, max = 10.0)]
+    pub exposure: f64,
+}
+
+impl Default for BuddhabrotParamUI {
+    fn default() -> Self {
+        Self {
+            anti: false,
+            max_iter_r: 5000,
+            max_iter_g: 500,
+            max_iter_b: 50,
+            samples: 1,
+            passes: 100,
+            exposure: 0.15,
+        }
+    }
+}
+
+impl BuddhabrotParamUI {
+    pub fn get_c_struct(&self) -> BuddhabrotParam {
+        BuddhabrotParam {
+            max_iter: [self.max_iter_r, self.max_iter_g, self.max_iter_b],
+            anti: self.anti as i32,
+            samples: self.samples,
+        }
+    }
+}
+
+/// Bookkeeping of the accumulated histograms, kept by the backends next to their buffers
+#[derive(Default)]
+pub struct HistogramState {
+    inputs: Option<(SFParam, BuddhabrotParam)>,
+    pub passes: usize,
+    pub samples: usize,
+}
+
+impl HistogramState {
+    /// Counts in the next pass of `n_samples` orbits, true when the histograms were accumulated
+    /// for other inputs and need clearing first
+    pub fn begin_pass(
+        &mut self,
+        fparam: SFParam,
+        buddha: BuddhabrotParam,
+        n_samples: usize,
+    ) -> bool {
+        let clear = self.inputs != Some((fparam, buddha));
+        if clear {
+            self.inputs = Some((fparam, buddha));
+            self.passes = 0;
+            self.samples = 0;
+        }
+        self.passes += 1;
+        self.samples += n_samples;
+        clear
+    }
+
+    /// Factors taking the hit counts of each histogram to densities, relative to every sample
+    /// hitting a single point spread evenly over the sampled square, and scaled by `exposure`.
+    /// Bounded orbits all run to the iteration limit, so anti densities are also per orbit point.
+    pub fn density_scales(&self, dims: (usize, usize), exposure: f64) -> [f64; 3] {
+        let Some((fparam, buddha)) = self.inputs else {
+            return [0.0; 3];
+        };
+        let view = fparam.view;
+        let pixel_area =
+            (view.right - view.left) * (view.top - view.bot) / (dims.0 * dims.1) as f64;
+        let expected = self.samples as f64 * pixel_area / (SAMPLE_SQUARE_SIDE * SAMPLE_SQUARE_SIDE);
+        buddha.max_iter.map(|max_iter| {
+            let points = if buddha.anti != 0 {
+                max_iter.max(1) as f64
+            } else {
+                1.0
+            };
+            exposure / (expected * points)
+        })
+    }
+}
@@ -0,0 +1,65 @@
+//! Conversions between the 8 bit sRGB of `Pixel_t`, linear light (in which colours are averaged)
+//! and OKLab (perceptually even gradients)
+
+use std::sync::OnceLock;
+
+fn srgb_lut() -> &'static [f32; 256] {
+    static LUT: OnceLock<[f32; 256]> = OnceLock::new();
+    LUT.get_or_init(|| {
+        let mut lut = [0.0; 256];
+        for (v, l) in lut.iter_mut().enumerate() {
+            let v = v as f32 / 255.0;
+            *l = if v <= 0.04045 {
+                v / 12.92
+            } else {
+                ((v + 0.055) / 1.055).powf(2.4)
+            };
+        }
+        lut
+    })
+}
+
+pub fn srgb_to_linear(v: u8) -> f32 {
+    srgb_lut()[v as usize]
+}
+
+pub fn linear_to_srgb(l: f32) -> u8 {
+    let l = l.clamp(0.0, 1.0);
+    let v = if l <= 0.0031308 {
+        l * 12.92
+    } else {
+        1.055 * l.powf(1.0 / 2.4) - 0.055
+    };
+    (v * 255.0).round() as u8
+}
+
+pub fn srgb_to_linear3(c: [u8; 3]) -> [f32; 3] {
+    c.map(srgb_to_linear)
+}
+
+pub fn linear_to_srgb3(c: [f32; 3]) -> [u8; 3] {
+    c.map(linear_to_srgb)
+}
+
+/// Linear sRGB to OKLab, see https://bottosson.github.io/posts/oklab/
+pub fn linear_to_oklab([r, g, b]: [f32; 3]) -> [f32; 3] {
+    let l = (0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+    let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+    let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+    [
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    ]
+}
+
+pub fn oklab_to_linear([ok_l, ok_a, ok_b]: [f32; 3]) -> [f32; 3] {
+    let l = (ok_l + 0.396_337_78 * ok_a + 0.215_803_76 * ok_b).powi(3);
+    let m = (ok_l - 0.105_561_346 * ok_a - 0.063_854_17 * ok_b).powi(3);
+    let s = (ok_l - 0.089_484_18 * ok_a - 1.291_485_5 * ok_b).powi(3);
+    [
+        4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s,
+        -1.268_438 * l + 2.609_757_4 * m - 0.341_319_38 * s,
+        -0.004_196_086_3 * l - 0.703_418_6 * m + 1.707_614_7 * s,
+    ]
+}
@@ -0,0 +1,1164 @@
+//! Pure rust mirror of the kernels in `ocl/`, parallelised over image rows with rayon. Only the
+//! built in formulas are available, custom OpenCL code is ignored (z^2+c stands in for it).
+
+use egui_inspect::logging::log::error;
+use ndarray::{Array2, Array3};
+use rayon::prelude::*;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::backend::{BackendResult, RenderBackend};
+use crate::buddhabrot::HistogramState;
+use crate::image_io::load_decoded;
+use crate::shape_trap::TrapOutput;
+use crate::wrapper_types::{
+    BBox, Bailout, BuddhabrotParam, Complex, DistanceParams, FormulaParams, Freqs, InteriorChecks,
+    LyapunovParam, NewtonParam, PaletteMapping, ProxType, SFParam, SceneParam, ShapeTrapParam,
+    SmoothParams, TrapShape, Vec3,
+};
+
+/// principal branch of z^p for real p
+fn powf(z: Complex, p: f64) -> Complex {
+    let r = z.norm_sqr().sqrt();
+    if r == 0.0 {
+        return z;
+    }
+    let rp = r.powf(p);
+    let th = z.im.atan2(z.re) * p;
+    Complex {
+        re: rp * th.cos(),
+        im: rp * th.sin(),
+    }
+}
+
+/// see `_iterate` in mandelutils.c, the custom function falls back to z^2+c
+fn f(z: Complex, c: Complex, formula: &FormulaParams) -> Complex {
+    let w = match formula.id {
+        2 => Complex {
+            re: z.re.abs(),
+            im: if formula.flip != 0 {
+                -z.im.abs()
+            } else {
+                z.im.abs()
+            },
+        },
+        3 => Complex {
+            re: z.re,
+            im: -z.im,
+        },
+        4 => return powf(z, formula.power) + c,
+        _ => z,
+    };
+    let mut w2 = w * w;
+    if formula.id == 5 || formula.id == 6 {
+        w2.re = w2.re.abs();
+    }
+    if formula.id == 6 {
+        w2.im = w2.im.abs();
+    }
+    w2 + c
+}
+
+/// see `_iterate_df_dz` in mandelutils.c
+fn df_dz(z: Complex, formula: &FormulaParams) -> Complex {
+    match formula.id {
+        0 | 1 => z + z,
+        4 => {
+            Complex {
+                re: formula.power,
+                im: 0.0,
+            } * powf(z, formula.power - 1.0)
+        }
+        _ => Complex {
+            re: 2.0 * z.norm_sqr().sqrt(),
+            im: 0.0,
+        },
+    }
+}
+
+fn in_box(z: Complex, b: &BBox) -> bool {
+    z.re > b.left && z.re < b.right && z.im > b.bot && z.im < b.top
+}
+
+/// see `in_bounds` in mandelutils.c, the custom norm falls back to |z| < 2
+fn in_bounds(z: Complex, bailout: &Bailout) -> bool {
+    let r = bailout.radius;
+    match bailout.norm {
+        1 => z.re.abs() < r && z.im.abs() < r,
+        2 => z.re.abs() < r,
+        3 => z.re.abs() + z.im.abs() < r,
+        4 => z.norm_sqr() < 4.0,
+        _ => z.norm_sqr() < r * r,
+    }
+}
+
+fn proximity(z: Complex, prox_type: &ProxType) -> f64 {
+    let mut res = 1000.0_f64;
+    if prox_type.to_unit_circ {
+        res = res.min(z.norm_sqr());
+    }
+    if prox_type.to_horizontal {
+        res = res.min(z.re.abs());
+    }
+    if prox_type.to_vertical {
+        res = res.min(z.im.abs());
+    }
+    res
+}
+
+/// see `_bulb_period` in mandelutils.c
+fn bulb_period(c: Complex) -> i32 {
+    let x = c.re - 0.25;
+    let y2 = c.im * c.im;
+    let q = x * x + y2;
+    if q * (q + x) <= 0.25 * y2 {
+        1
+    } else if (c.re + 1.0) * (c.re + 1.0) + y2 <= 0.0625 {
+        2
+    } else {
+        0
+    }
+}
+
+/// `interior` with its tolerance in the plane rather than in pixels, see `_plane_interior` in
+/// mandelutils.c
+fn plane_interior(interior: &InteriorChecks, view: &BBox, m: usize) -> InteriorChecks {
+    InteriorChecks {
+        tolerance: interior.tolerance * (view.right - view.left) / (m as f64),
+        ..*interior
+    }
+}
+
+/// Brent's cycle detection, see `_brent_step` in mandelutils.c
+struct Brent {
+    check: Complex,
+    power: i32,
+    lam: i32,
+    tol2: f64,
+}
+
+impl Brent {
+    fn new(z: Complex, interior: &InteriorChecks) -> Self {
+        Self {
+            check: z,
+            power: 1,
+            lam: 0,
+            tol2: interior.tolerance * interior.tolerance,
+        }
+    }
+
+    /// The period once `z` revisits the saved point, 0 until then
+    fn step(&mut self, z: Complex) -> i32 {
+        self.lam += 1;
+        if (z - self.check).norm_sqr() < self.tol2 {
+            return self.lam;
+        }
+        if self.lam == self.power {
+            self.check = z;
+            self.power *= 2;
+            self.lam = 0;
+        }
+        0
+    }
+}
+
+/// Iterations to escape and the period found by the interior checks, see `_escape_iter` in
+/// mandelutils.c
+fn escape_iter(
+    mut z: Complex,
+    c: Complex,
+    max_iter: i32,
+    formula: &FormulaParams,
+    interior: &InteriorChecks,
+    bailout: &Bailout,
+) -> (i32, i32) {
+    if interior.bulbs != 0 {
+        let period = bulb_period(c);
+        if period != 0 {
+            return (max_iter, period);
+        }
+    }
+
+    let mut brent = Brent::new(z, interior);
+    let mut i = 0;
+    while i < max_iter && in_bounds(z, bailout) {
+        z = f(z, c, formula);
+        i += 1;
+        if interior.periodicity != 0 {
+            let period = brent.step(z);
+            if period != 0 {
+                return (max_iter, period);
+            }
+        }
+    }
+    (i, 0)
+}
+
+fn smooth_count(i: i32, norm: f64, prev_norm: f64, smooth: &SmoothParams) -> f64 {
+    let mut d = smooth.degree;
+    if smooth.estimate_degree && prev_norm > 1.0 {
+        let d_est = norm.ln() / prev_norm.ln();
+        if d_est > 1.0 {
+            d = d_est;
+        }
+    }
+
+    let nu = (0.5 * norm.ln() / smooth.bailout.ln()).ln() / d.ln();
+    (i + 1) as f64 - nu
+}
+
+/// normalised iteration count, see `_smooth_escape_iter` in mandelutils.c
+fn smooth_escape_iter(
+    mut z: Complex,
+    c: Complex,
+    max_iter: i32,
+    smooth: &SmoothParams,
+    formula: &FormulaParams,
+    interior: &InteriorChecks,
+) -> f64 {
+    if interior.bulbs != 0 && bulb_period(c) != 0 {
+        return max_iter as f64;
+    }
+
+    let r2 = smooth.bailout * smooth.bailout;
+    let mut norm = z.norm_sqr();
+    let mut prev_norm = norm;
+    let mut brent = Brent::new(z, interior);
+    let mut i = 0;
+    while i < max_iter && norm < r2 {
+        z = f(z, c, formula);
+        prev_norm = norm;
+        norm = z.norm_sqr();
+        i += 1;
+        if interior.periodicity != 0 && brent.step(z) != 0 {
+            return max_iter as f64;
+        }
+    }
+
+    if i == max_iter {
+        return max_iter as f64;
+    }
+
+    smooth_count(i, norm, prev_norm, smooth)
+}
+
+/// see `_distance_estimate` in mandelutils.c
+fn distance_estimate(
+    mut z: Complex,
+    c: Complex,
+    mandel: bool,
+    max_iter: i32,
+    distance: &DistanceParams,
+    formula: &FormulaParams,
+) -> f64 {
+    let mut dz = Complex { re: 1.0, im: 0.0 };
+    let bailout2 = distance.bailout * distance.bailout;
+    for _ in 0..max_iter {
+        let norm = z.norm_sqr();
+        if norm > bailout2 {
+            let r = norm.sqrt();
+            return 0.5 * r * r.ln() / dz.norm_sqr().sqrt();
+        }
+        dz = df_dz(z, formula) * dz;
+        if mandel {
+            dz = dz + Complex { re: 1.0, im: 0.0 };
+        }
+        z = f(z, c, formula);
+    }
+    0.0
+}
+
+/// see `_perturbed_escape_iter` in mandelutils.c
+fn perturbed_escape_iter(
+    mut dz: Complex,
+    dc: Complex,
+    reference: &[Complex],
+    mut m: usize,
+    max_iter: i32,
+    smooth: Option<&SmoothParams>,
+) -> f64 {
+    let r2 = smooth.map_or(4.0, |s| s.bailout * s.bailout);
+    let mut z = reference[m] + dz;
+    let mut norm = z.norm_sqr();
+    let mut prev_norm = norm;
+    let mut i = 0;
+    while i < max_iter && norm < r2 {
+        if m == reference.len() - 1 || norm < dz.norm_sqr() {
+            dz = z - reference[0];
+            m = 0;
+        }
+        dz = (reference[m] + reference[m] + dz) * dz + dc;
+        m += 1;
+        i += 1;
+        z = reference[m] + dz;
+        prev_norm = norm;
+        norm = z.norm_sqr();
+    }
+
+    match smooth {
+        Some(smooth) if i < max_iter => smooth_count(i, norm, prev_norm, smooth),
+        _ => i as f64,
+    }
+}
+
+fn min_prox(
+    mut z: Complex,
+    c: Complex,
+    max_iter: i32,
+    prox_type: &ProxType,
+    formula: &FormulaParams,
+    bailout: &Bailout,
+) -> f64 {
+    let mut i = 0;
+    let mut dist = proximity(z, prox_type);
+    while i < max_iter && in_bounds(z, bailout) {
+        z = f(z, c, formula);
+        dist = dist.min(proximity(z, prox_type));
+        i += 1;
+    }
+    dist
+}
+
+/// returns UV coords in given box
+fn orbit_trap(
+    mut z: Complex,
+    c: Complex,
+    b: &BBox,
+    max_iter: i32,
+    formula: &FormulaParams,
+) -> Complex {
+    for _ in 0..max_iter {
+        z = f(z, c, formula);
+        if in_box(z, b) {
+            return Complex {
+                re: (z.re - b.left) / (b.right - b.left),
+                im: (z.im - b.bot) / (b.top - b.bot),
+            };
+        }
+    }
+    Complex { re: 0.0, im: 0.0 }
+}
+
+fn segment_distance(z: Complex, a: Complex, b: Complex) -> f64 {
+    let pa = z - a;
+    let ba = b - a;
+    let h = ((pa.re * ba.re + pa.im * ba.im) / ba.norm_sqr()).clamp(0.0, 1.0);
+    (pa - Complex {
+        re: ba.re * h,
+        im: ba.im * h,
+    })
+    .norm_sqr()
+    .sqrt()
+}
+
+/// see `_polygon_distance` in mandelutils.c
+fn polygon_distance(z: Complex, sides: i32) -> f64 {
+    let step = std::f64::consts::TAU / sides as f64;
+    let vertex = |k: i32| Complex {
+        re: (k as f64 * step).cos(),
+        im: (k as f64 * step).sin(),
+    };
+    (0..sides)
+        .map(|k| segment_distance(z, vertex(k), vertex(k + 1)))
+        .fold(1000.0, f64::min)
+}
+
+/// Distance from z to a trap shape and z in the shape's own frame, see `_shape_distance` in
+/// mandelutils.c, the custom shape falls back to a point
+fn shape_distance(z: Complex, shape: &TrapShape) -> (f64, Complex) {
+    let w = z - shape.offset;
+    let (sa, ca) = shape.angle.sin_cos();
+    let w = Complex {
+        re: (ca * w.re + sa * w.im) / shape.scale,
+        im: (ca * w.im - sa * w.re) / shape.scale,
+    };
+    let unit_re = (Complex { re: -1.0, im: 0.0 }, Complex { re: 1.0, im: 0.0 });
+    let unit_im = (Complex { re: 0.0, im: -1.0 }, Complex { re: 0.0, im: 1.0 });
+    let d = match shape.kind {
+        1 => (w.norm_sqr().sqrt() - 1.0).abs(),
+        2 => segment_distance(w, unit_re.0, unit_re.1),
+        3 => {
+            segment_distance(w, unit_re.0, unit_re.1).min(segment_distance(w, unit_im.0, unit_im.1))
+        }
+        4 => polygon_distance(w, shape.sides),
+        _ => w.norm_sqr().sqrt(),
+    };
+    (d * shape.scale, w)
+}
+
+/// see `_shape_trap` in mandelutils.c
+fn shape_trap(
+    mut z: Complex,
+    c: Complex,
+    max_iter: i32,
+    formula: &FormulaParams,
+    bailout: &Bailout,
+    trap: &ShapeTrapParam,
+) -> (f64, Complex) {
+    let to_uv = |w: Complex| Complex {
+        re: 0.5 + 0.5 * w.re,
+        im: 0.5 + 0.5 * w.im,
+    };
+    let mut best = 1e30;
+    let mut sum = 0.0;
+    let mut hit = Complex { re: -1.0, im: -1.0 };
+    let mut i = 0;
+    while i < max_iter && in_bounds(z, bailout) {
+        z = f(z, c, formula);
+        i += 1;
+
+        let (d, nearest) = trap.shapes[..trap.n_shapes as usize]
+            .iter()
+            .map(|shape| shape_distance(z, shape))
+            .fold((1e30, hit), |a, b| if b.0 < a.0 { b } else { a });
+
+        sum += d;
+        if d < best {
+            best = d;
+            hit = nearest;
+        }
+        if trap.mode == 2 && d < trap.threshold {
+            return (d / trap.threshold, to_uv(hit));
+        }
+    }
+
+    match trap.mode {
+        2 => (1.0, Complex { re: 0.0, im: 0.0 }),
+        1 => (sum / i.max(1) as f64, to_uv(hit)),
+        _ => (best, to_uv(hit)),
+    }
+}
+
+/// see `_newton_step` in mandelutils.c
+fn newton_step(z: Complex, c: Complex, newton: &NewtonParam) -> Complex {
+    let degree = newton.degree as usize;
+    let mut p = newton.coeffs[degree];
+    let mut dp = Complex { re: 0.0, im: 0.0 };
+    for &a in newton.coeffs[..degree].iter().rev() {
+        dp = dp * z + p;
+        p = p * z + a;
+    }
+
+    let z = z - newton.relaxation * (p / dp);
+    if newton.nova != 0 {
+        z + c
+    } else {
+        z
+    }
+}
+
+/// Iterations to converge and the index of the nearest root then, see `_newton` in mandelutils.c
+fn newton(mut z: Complex, c: Complex, max_iter: i32, newton: &NewtonParam) -> (i32, Option<usize>) {
+    let tol2 = newton.tolerance * newton.tolerance;
+    for i in 0..max_iter {
+        let next = newton_step(z, c, newton);
+        let d = next - z;
+        z = next;
+        if d.norm_sqr() < tol2 {
+            let root = newton.roots[..newton.degree as usize]
+                .iter()
+                .map(|&r| (z - r).norm_sqr())
+                .enumerate()
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(k, _)| k);
+            return (i + 1, root);
+        }
+    }
+    (max_iter, None)
+}
+
+/// see `_lyapunov` in mandelutils.c
+fn lyapunov(a: f64, b: f64, max_iter: i32, lyapunov: &LyapunovParam) -> f64 {
+    let mut x = 0.5;
+    let mut sum = 0.0;
+    let mut k = 0;
+    for i in 0..lyapunov.warmup + max_iter {
+        let r = if (lyapunov.sequence >> k) & 1 != 0 {
+            b
+        } else {
+            a
+        };
+        k = if k + 1 == lyapunov.length { 0 } else { k + 1 };
+        if i >= lyapunov.warmup {
+            sum += (r * (1.0 - 2.0 * x)).abs().ln();
+        }
+        x = r * x * (1.0 - x);
+    }
+
+    let exponent = sum / max_iter as f64;
+    if exponent.is_nan() {
+        return 0.0;
+    }
+    1.0 / (1.0 + (exponent * lyapunov.contrast).exp())
+}
+
+/// see `_hash` in mandelutils.c
+fn hash(x: u32) -> u32 {
+    let state = x.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+fn uniform(state: &mut u32) -> f64 {
+    *state = hash(*state);
+    *state as f64 / 4294967296.0
+}
+
+fn splat(z: Complex, view: &BBox, (n, m): (usize, usize), hist: &[AtomicU32]) {
+    let u = (z.re - view.left) / (view.right - view.left) * m as f64;
+    let v = (z.im - view.bot) / (view.top - view.bot) * n as f64;
+    if u >= 0.0 && u < m as f64 && v >= 0.0 && v < n as f64 {
+        hist[(v as usize) * m + u as usize].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// see `_buddhabrot` in mandelutils.c
+fn buddhabrot(
+    mut z: Complex,
+    c: Complex,
+    fparam: &SFParam,
+    dims: (usize, usize),
+    buddha: &BuddhabrotParam,
+    hists: &[Vec<AtomicU32>; 3],
+) {
+    let max_iter = buddha.max_iter.iter().copied().max().unwrap_or(0);
+    let (n, _) = escape_iter(
+        z,
+        c,
+        max_iter,
+        &fparam.formula,
+        &plane_interior(&fparam.interior, &fparam.view, dims.1),
+        &fparam.bailout,
+    );
+    let splatted = buddha
+        .max_iter
+        .map(|mk| if buddha.anti != 0 { n >= mk } else { n < mk });
+    if !splatted.contains(&true) {
+        return;
+    }
+
+    for t in 0..n {
+        z = f(z, c, &fparam.formula);
+        for k in 0..3 {
+            if splatted[k] && t < buddha.max_iter[k] {
+                splat(z, &fparam.view, dims, &hists[k]);
+            }
+        }
+    }
+}
+
+/// see `_trap_proximity` in mandelutils.c
+fn trap_proximity(z: Vec3, trap: &ProxType) -> f64 {
+    let mut res = 1000.0_f64;
+    if trap.to_unit_circ {
+        res = res.min(z.dot(z));
+    }
+    if trap.to_horizontal {
+        res = res.min(z.x.abs());
+    }
+    if trap.to_vertical {
+        res = res.min(z.y.abs());
+    }
+    res
+}
+
+fn mandelbulb_de(pos: Vec3, scene: &SceneParam, trap: &mut f64) -> f64 {
+    let mut z = pos;
+    let mut dr = 1.0;
+    let mut r = z.length();
+    for _ in 0..scene.iters {
+        if r >= 2.0 {
+            break;
+        }
+        *trap = trap.min(trap_proximity(z, &scene.trap));
+        let theta = if r > 0.0 {
+            (z.z / r).acos() * scene.power
+        } else {
+            0.0
+        };
+        let phi = z.y.atan2(z.x) * scene.power;
+        dr = r.powf(scene.power - 1.0) * scene.power * dr + 1.0;
+        let zr = r.powf(scene.power);
+        z = Vec3::new(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            theta.cos(),
+        ) * zr
+            + pos;
+        r = z.length();
+    }
+    0.5 * r.ln() * r / dr
+}
+
+fn mandelbox_de(pos: Vec3, scene: &SceneParam, trap: &mut f64) -> f64 {
+    let mut z = pos;
+    let mut dr = 1.0;
+    let min_r2 = scene.min_radius * scene.min_radius;
+    let l = scene.fold_limit;
+    let fold = |c: f64| c.clamp(-l, l) * 2.0 - c;
+    for _ in 0..scene.iters {
+        z = Vec3::new(fold(z.x), fold(z.y), fold(z.z));
+        let r2 = z.dot(z);
+        let k = if r2 < min_r2 {
+            1.0 / min_r2
+        } else if r2 < 1.0 {
+            1.0 / r2
+        } else {
+            1.0
+        };
+        z = z * (k * scene.box_scale) + pos;
+        dr = dr * k * scene.box_scale.abs() + 1.0;
+        *trap = trap.min(trap_proximity(z, &scene.trap));
+    }
+    z.length() / dr
+}
+
+/// see `_scene_de` in mandelutils.c
+fn scene_de(p: Vec3, scene: &SceneParam, trap: &mut f64) -> f64 {
+    *trap = 1000.0;
+    if scene.shape == 1 {
+        mandelbox_de(p, scene, trap)
+    } else {
+        mandelbulb_de(p, scene, trap)
+    }
+}
+
+fn scene_normal(p: Vec3, scene: &SceneParam, h: f64) -> Vec3 {
+    let mut trap = 0.0;
+    let mut diff = |d: Vec3| scene_de(p + d, scene, &mut trap) - scene_de(p - d, scene, &mut trap);
+    Vec3::new(
+        diff(Vec3::new(h, 0.0, 0.0)),
+        diff(Vec3::new(0.0, h, 0.0)),
+        diff(Vec3::new(0.0, 0.0, h)),
+    )
+    .normalized()
+}
+
+/// see `_soft_shadow` in mandelutils.c
+fn soft_shadow(p: Vec3, scene: &SceneParam, start: f64) -> f64 {
+    let mut res = 1.0_f64;
+    let mut t = start;
+    let mut trap = 0.0;
+    for _ in 0..scene.max_steps {
+        if t >= scene.max_dist {
+            break;
+        }
+        let h = scene_de(p + scene.light_dir * t, scene, &mut trap);
+        if h < scene.detail * t {
+            return 0.0;
+        }
+        res = res.min(scene.softness * h / t);
+        t += h;
+    }
+    res
+}
+
+/// see `_ambient_occlusion` in mandelutils.c
+fn ambient_occlusion(p: Vec3, n: Vec3, scene: &SceneParam) -> f64 {
+    let mut occ = 0.0;
+    let mut w = 1.0;
+    let mut trap = 0.0;
+    for i in 1..=5 {
+        let h = 0.01 + 0.03 * i as f64;
+        occ += w * (h - scene_de(p + n * h, scene, &mut trap));
+        w *= 0.5;
+    }
+    (1.0 - 3.0 * scene.ao * occ).clamp(0.0, 1.0)
+}
+
+/// see `_ray_march` in mandelutils.c
+fn ray_march(eye: Vec3, dir: Vec3, scene: &SceneParam) -> [f64; 3] {
+    let mut t = 0.0;
+    let mut trap = 0.0;
+    let mut hit = false;
+    for _ in 0..scene.max_steps {
+        if t >= scene.max_dist {
+            break;
+        }
+        let d = scene_de(eye + dir * t, scene, &mut trap);
+        if d < scene.detail * t {
+            hit = true;
+            break;
+        }
+        t += d;
+    }
+
+    if !hit {
+        let s = 0.5 + 0.5 * dir.z;
+        return [0.02 + 0.08 * s, 0.02 + 0.1 * s, 0.05 + 0.15 * s];
+    }
+
+    let p = eye + dir * t;
+    let eps = scene.detail * t;
+    let n = scene_normal(p, scene, eps);
+    scene_de(p, scene, &mut trap);
+    let freqs = scene.trap_freqs;
+    let base = [freqs.r, freqs.g, freqs.b].map(|freq| 0.5 + 0.5 * (trap * freq).sin());
+
+    let shadow = soft_shadow(p + n * (2.0 * eps), scene, 2.0 * eps);
+    let ao = ambient_occlusion(p, n, scene);
+    let nl = n.dot(scene.light_dir);
+    let diffuse = nl.max(0.0) * shadow;
+    let refl = n * (2.0 * nl) - scene.light_dir;
+    let spec = scene.specular * (-refl.dot(dir)).max(0.0).powf(scene.shininess) * shadow;
+    let light = 0.2 * ao + 0.8 * diffuse;
+
+    base.map(|b| (b * light + spec).clamp(0.0, 1.0).powf(1.0 / 2.2))
+}
+
+/// Evaluates `func(z_0, c)` for every pixel of the view
+fn fill_field(
+    field: &mut Array2<f64>,
+    fparam: &SFParam,
+    func: impl Fn(Complex, Complex) -> f64 + Sync,
+) {
+    let (n, m) = field.dim();
+    let view = fparam.view;
+    field
+        .as_slice_mut()
+        .unwrap()
+        .par_chunks_mut(m)
+        .enumerate()
+        .for_each(|(i, row)| {
+            for (j, val) in row.iter_mut().enumerate() {
+                let p = Complex {
+                    re: view.left + (j as f64) * (view.right - view.left) / (m as f64),
+                    im: view.bot + (i as f64) * (view.top - view.bot) / (n as f64),
+                };
+                let c = if fparam.mode_int != 0 { p } else { fparam.c };
+                *val = func(p, c);
+            }
+        });
+}
+
+/// Scaled and offset, then clamped, repeated or mirrored into [0, 1], see `_palette_coord` in
+/// mandelutils.c
+fn palette_coord(v: f64, mapping: PaletteMapping) -> f64 {
+    let mut u = v * mapping.scale + mapping.offset;
+    if mapping.repeat_mode == 1 {
+        u -= u.floor();
+    } else if mapping.repeat_mode == 2 {
+        u -= 2.0 * (u / 2.0).floor();
+        if u > 1.0 {
+            u = 2.0 - u;
+        }
+    }
+    u.clamp(0.0, 1.0)
+}
+
+/// Writes `func(pixel_index)` into every pixel of the rgb image
+fn fill_rgb(rgb: &mut Array3<u8>, func: impl Fn(usize) -> [u8; 3] + Sync) {
+    rgb.as_slice_mut()
+        .unwrap()
+        .par_chunks_mut(3)
+        .enumerate()
+        .for_each(|(fi, px)| px.copy_from_slice(&func(fi)));
+}
+
+fn blinterp_f(tl: f64, tr: f64, bl: f64, br: f64, u: f64, v: f64) -> f64 {
+    let ta = (1.0 - u) * tl + u * tr;
+    let ba = (1.0 - u) * bl + u * br;
+    (1.0 - v) * ta + v * ba
+}
+
+/// CPU backend
+pub struct CpuCompute {
+    field_1: Array2<f64>,
+    field_2: Array2<f64>,
+    field_3: Array2<f64>,
+    histograms: [Vec<AtomicU32>; 3],
+    histogram_state: HistogramState,
+    sampled_path: Option<PathBuf>,
+    sampled_rgb: Option<Array3<u8>>,
+    rgb: Array3<u8>,
+}
+
+impl CpuCompute {
+    pub fn new(im_dims: (usize, usize)) -> Self {
+        let (n, m) = im_dims;
+        Self {
+            field_1: Array2::zeros(im_dims),
+            field_2: Array2::zeros(im_dims),
+            field_3: Array2::zeros(im_dims),
+            histograms: std::array::from_fn(|_| (0..n * m).map(|_| AtomicU32::new(0)).collect()),
+            histogram_state: Default::default(),
+            sampled_path: None,
+            sampled_rgb: None,
+            rgb: Array3::zeros((n, m, 3)),
+        }
+    }
+
+    fn field_mut(&mut self, i: usize) -> &mut Array2<f64> {
+        match i {
+            1 => &mut self.field_1,
+            2 => &mut self.field_2,
+            3 => &mut self.field_3,
+            _ => {
+                panic!("invalid field index");
+            }
+        }
+    }
+}
+
+impl RenderBackend for CpuCompute {
+    fn sampled_path(&self) -> Option<&PathBuf> {
+        self.sampled_path.as_ref()
+    }
+
+    fn has_sampled(&self) -> bool {
+        self.sampled_rgb.is_some()
+    }
+
+    fn update_sampled(&mut self, ip: PathBuf) {
+        match load_decoded(&ip) {
+            Ok(sampled) => {
+                self.sampled_rgb = Some(sampled);
+                self.sampled_path = Some(ip);
+            }
+            Err(err) => error!("{err}"),
+        }
+    }
+
+    fn resize(&mut self, im_dims: (usize, usize)) {
+        *self = Self {
+            sampled_path: self.sampled_path.take(),
+            sampled_rgb: self.sampled_rgb.take(),
+            ..Self::new(im_dims)
+        };
+    }
+
+    fn run_escape_iter(&mut self, fi: usize, fparam: SFParam) -> BackendResult<()> {
+        let max_iter = fparam.max_iter;
+        let interior = plane_interior(&fparam.interior, &fparam.view, self.field_1.dim().1);
+        fill_field(self.field_mut(fi), &fparam, |z, c| {
+            let (n, _) = escape_iter(z, c, max_iter, &fparam.formula, &interior, &fparam.bailout);
+            (n as f64) / (max_iter as f64)
+        });
+        Ok(())
+    }
+
+    fn run_interior_period(&mut self, fi: usize, fparam: SFParam) -> BackendResult<()> {
+        let max_iter = fparam.max_iter;
+        let interior = InteriorChecks {
+            periodicity: 1,
+            ..plane_interior(&fparam.interior, &fparam.view, self.field_1.dim().1)
+        };
+        fill_field(self.field_mut(fi), &fparam, |z, c| {
+            escape_iter(z, c, max_iter, &fparam.formula, &interior, &fparam.bailout).1 as f64
+        });
+        Ok(())
+    }
+
+    fn run_smooth_escape_iter(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        smooth: SmoothParams,
+    ) -> BackendResult<()> {
+        let max_iter = fparam.max_iter;
+        let interior = plane_interior(&fparam.interior, &fparam.view, self.field_1.dim().1);
+        fill_field(self.field_mut(fi), &fparam, |z, c| {
+            smooth_escape_iter(z, c, max_iter, &smooth, &fparam.formula, &interior)
+                / (max_iter as f64)
+        });
+        Ok(())
+    }
+
+    fn run_perturbed_escape_iter(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        reference: &[Complex],
+        smooth: Option<SmoothParams>,
+    ) -> BackendResult<()> {
+        let max_iter = fparam.max_iter;
+        let mandel = fparam.mode_int != 0;
+        let m = if mandel { 1 } else { 0 };
+        fill_field(self.field_mut(fi), &fparam, |dp, _| {
+            let dc = if mandel {
+                dp
+            } else {
+                Complex { re: 0.0, im: 0.0 }
+            };
+            perturbed_escape_iter(dp, dc, reference, m, max_iter, smooth.as_ref())
+                / (max_iter as f64)
+        });
+        Ok(())
+    }
+
+    fn run_distance_estimate(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        distance: DistanceParams,
+    ) -> BackendResult<()> {
+        let max_iter = fparam.max_iter;
+        let mandel = fparam.mode_int != 0;
+        let view = fparam.view;
+        let pixel = (view.right - view.left) / self.field_1.ncols() as f64;
+        fill_field(self.field_mut(fi), &fparam, |z, c| {
+            let d = distance_estimate(z, c, mandel, max_iter, &distance, &fparam.formula);
+            (d / (pixel * distance.thickness)).min(1.0)
+        });
+        Ok(())
+    }
+
+    fn run_min_prox(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        prox_type: ProxType,
+    ) -> BackendResult<()> {
+        let max_iter = fparam.max_iter;
+        fill_field(self.field_mut(fi), &fparam, |z, c| {
+            min_prox(z, c, max_iter, &prox_type, &fparam.formula, &fparam.bailout)
+        });
+        Ok(())
+    }
+
+    fn run_box_trap_partial(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        box_: BBox,
+        real: bool,
+    ) -> BackendResult<()> {
+        let max_iter = fparam.max_iter;
+        fill_field(self.field_mut(fi), &fparam, |z, c| {
+            let uv = orbit_trap(z, c, &box_, max_iter, &fparam.formula);
+            if real {
+                uv.re
+            } else {
+                uv.im
+            }
+        });
+        Ok(())
+    }
+
+    fn run_shape_trap(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        trap: ShapeTrapParam,
+        output: TrapOutput,
+    ) -> BackendResult<()> {
+        let max_iter = fparam.max_iter;
+        fill_field(self.field_mut(fi), &fparam, |z, c| {
+            let (d, uv) = shape_trap(z, c, max_iter, &fparam.formula, &fparam.bailout, &trap);
+            match output {
+                TrapOutput::Distance => d,
+                TrapOutput::HitRe => uv.re,
+                TrapOutput::HitIm => uv.im,
+            }
+        });
+        Ok(())
+    }
+
+    fn run_newton(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        newton_param: NewtonParam,
+        root: bool,
+    ) -> BackendResult<()> {
+        let max_iter = fparam.max_iter;
+        let start = (newton_param.nova != 0 && fparam.mode_int != 0).then_some(newton_param.start);
+        fill_field(self.field_mut(fi), &fparam, |p, c| {
+            let (i, k) = newton(start.unwrap_or(p), c, max_iter, &newton_param);
+            if root {
+                k.map_or(0.0, |k| (k + 1) as f64 / newton_param.degree as f64)
+            } else {
+                i as f64 / max_iter as f64
+            }
+        });
+        Ok(())
+    }
+
+    fn run_lyapunov(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        lyapunov_param: LyapunovParam,
+    ) -> BackendResult<()> {
+        let max_iter = fparam.max_iter;
+        fill_field(self.field_mut(fi), &fparam, |p, _| {
+            lyapunov(p.re + 2.0, p.im + 2.0, max_iter, &lyapunov_param)
+        });
+        Ok(())
+    }
+
+    fn run_buddhabrot_pass(
+        &mut self,
+        fparam: SFParam,
+        buddha: BuddhabrotParam,
+    ) -> BackendResult<()> {
+        let dims = self.field_1.dim();
+        let n_samples = dims.0 * dims.1 * buddha.samples as usize;
+        if self.histogram_state.begin_pass(fparam, buddha, n_samples) {
+            for hist in self.histograms.iter() {
+                hist.iter()
+                    .for_each(|count| count.store(0, Ordering::Relaxed));
+            }
+        }
+
+        let seed = self.histogram_state.passes as u32;
+        let hists = &self.histograms;
+        (0..dims.0 * dims.1).into_par_iter().for_each(|fi| {
+            let mut state = hash(seed ^ hash(fi as u32));
+            for _ in 0..buddha.samples {
+                let p = Complex {
+                    re: 4.0 * uniform(&mut state) - 2.0,
+                    im: 4.0 * uniform(&mut state) - 2.0,
+                };
+                let c = if fparam.mode_int != 0 { p } else { fparam.c };
+                buddhabrot(p, c, &fparam, dims, &buddha, hists);
+            }
+        });
+        Ok(())
+    }
+
+    fn histogram_state(&self) -> &HistogramState {
+        &self.histogram_state
+    }
+
+    fn run_map_histograms(&mut self, exposure: f64) -> BackendResult<()> {
+        let scales = self
+            .histogram_state
+            .density_scales(self.field_1.dim(), exposure);
+        let fields = [&mut self.field_1, &mut self.field_2, &mut self.field_3];
+        for ((field, hist), scale) in fields.into_iter().zip(self.histograms.iter()).zip(scales) {
+            field
+                .as_slice_mut()
+                .unwrap()
+                .par_iter_mut()
+                .zip(hist.par_iter())
+                .for_each(|(v, count)| {
+                    *v = 1.0 - (-scale * count.load(Ordering::Relaxed) as f64).exp()
+                });
+        }
+        Ok(())
+    }
+
+    fn run_ray_march(&mut self, fparam: SFParam, scene: SceneParam) -> BackendResult<()> {
+        let (n, m, _) = self.rgb.dim();
+        let view = fparam.view;
+        let frame = scene.frame;
+        let half_width = (frame.right - frame.left) / 2.0;
+        fill_rgb(&mut self.rgb, |fi| {
+            let (i, j) = (fi / m, fi % m);
+            let p = Complex {
+                re: view.left + (j as f64) * (view.right - view.left) / (m as f64),
+                im: view.bot + (i as f64) * (view.top - view.bot) / (n as f64),
+            };
+            // first image row at the top
+            let u = (p.re - (frame.left + frame.right) / 2.0) / half_width;
+            let v = ((frame.bot + frame.top) / 2.0 - p.im) / half_width;
+            let film = scene.right * u + scene.up * v;
+            let dir = (scene.forward + film * scene.fov_scale).normalized();
+            ray_march(scene.eye, dir, &scene).map(|c| (255.0 * c) as u8)
+        });
+        Ok(())
+    }
+
+    fn run_map_sines(&mut self, freqs: Freqs) -> BackendResult<()> {
+        let field = self.field_1.as_slice().unwrap();
+        let sine = |v: f64, freq: f64| (127.0 * ((v * freq).sin() + 1.0)) as u8;
+        fill_rgb(&mut self.rgb, |fi| {
+            let v = field[fi];
+            [sine(v, freqs.r), sine(v, freqs.g), sine(v, freqs.b)]
+        });
+        Ok(())
+    }
+
+    fn run_map_palette(&mut self, lut: &Array2<u8>, mapping: PaletteMapping) -> BackendResult<()> {
+        let field = self.field_1.as_slice().unwrap();
+        let len = lut.nrows();
+        fill_rgb(&mut self.rgb, |fi| {
+            let u = palette_coord(field[fi], mapping);
+            let row = lut.row(((u * len as f64) as usize).min(len - 1));
+            [row[0], row[1], row[2]]
+        });
+        Ok(())
+    }
+
+    fn run_map_basins(&mut self, colors: &Array2<u8>, shading: f64) -> BackendResult<()> {
+        let root = self.field_1.as_slice().unwrap();
+        let iters = self.field_2.as_slice().unwrap();
+        let n_roots = colors.nrows();
+        fill_rgb(&mut self.rgb, |fi| {
+            let k = (root[fi] * n_roots as f64).round() as usize;
+            if k == 0 {
+                return [0, 0, 0];
+            }
+            let color = colors.row(k.min(n_roots) - 1);
+            let b = (1.0 - iters[fi]).powf(shading);
+            [0, 1, 2].map(|l| (b * color[l] as f64) as u8)
+        });
+        Ok(())
+    }
+
+    fn run_pack(&mut self, normalise: bool) -> BackendResult<()> {
+        let r = self.field_1.as_slice().unwrap();
+        let g = self.field_2.as_slice().unwrap();
+        let b = self.field_3.as_slice().unwrap();
+        fill_rgb(&mut self.rgb, |fi| {
+            let s = if normalise {
+                r[fi] + g[fi] + b[fi]
+            } else {
+                1.0
+            };
+            [
+                (255.0 * r[fi] / s) as u8,
+                (255.0 * g[fi] / s) as u8,
+                (255.0 * b[fi] / s) as u8,
+            ]
+        });
+        Ok(())
+    }
+
+    fn run_map_img(&mut self, bilinear: bool) -> BackendResult<()> {
+        if let Some(sampled) = self.sampled_rgb.as_ref() {
+            let (h, w, _) = sampled.dim();
+            let u = self.field_1.as_slice().unwrap();
+            let v = self.field_2.as_slice().unwrap();
+            fill_rgb(&mut self.rgb, |fi| {
+                // expecting fields in [0, 1) range
+                if bilinear {
+                    let fi_ = (h as f64 * u[fi]).clamp(0.0, h as f64 - 2.0);
+                    let fj_ = (w as f64 * v[fi]).clamp(0.0, w as f64 - 2.0);
+                    let (i, j) = (fi_ as usize, fj_ as usize);
+                    let (du, dv) = (fi_ - i as f64, fj_ - j as f64);
+                    let mut px = [0; 3];
+                    for (k, c) in px.iter_mut().enumerate() {
+                        *c = blinterp_f(
+                            sampled[[i, j, k]] as f64,
+                            sampled[[i, j + 1, k]] as f64,
+                            sampled[[i + 1, j, k]] as f64,
+                            sampled[[i + 1, j + 1, k]] as f64,
+                            du,
+                            dv,
+                        ) as u8;
+                    }
+                    px
+                } else {
+                    let i = ((h as f64 * u[fi]).max(0.0) as usize).min(h - 1);
+                    let j = ((w as f64 * v[fi]).max(0.0) as usize).min(w - 1);
+                    [sampled[[i, j, 0]], sampled[[i, j, 1]], sampled[[i, j, 2]]]
+                }
+            });
+        }
+        Ok(())
+    }
+
+    fn read_rgb(&mut self) -> BackendResult<()> {
+        Ok(())
+    }
+
+    fn rgb(&self) -> &Array3<u8> {
+        &self.rgb
+    }
+
+    fn rgb_mut(&mut self) -> &mut Array3<u8> {
+        &mut self.rgb
+    }
+}
@@ -0,0 +1,124 @@
+//! Perturbation rendering past double precision. A reference orbit is iterated on the host at
+//! the (high precision) view center, pixels then only track their (double precision) offset
+//! from it, see `_perturbed_escape_iter` in mandelutils.c.
+
+use dashu_float::{round::mode::HalfAway, DBig, FBig};
+use egui_inspect::EguiInspect;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::wrapper_types::Complex;
+
+type HPFloat = FBig<HalfAway, 2>;
+
+/// Orbits are cut short once this far out, the kernel rebases onto the start of the orbit anyway
+static REFERENCE_ESCAPE_NORM: f64 = 1e12;
+
+/// High precision view center, takes the place of `view_center` when enabled
+#[derive(Clone, PartialEq, EguiInspect, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeepZoom {
+    pub enabled: bool,
+    pub center_re: String,
+    pub center_im: String,
+}
+
+fn parse_hp(s: &str, prec: usize) -> Result<HPFloat, String> {
+    let d = DBig::from_str(s.trim()).map_err(|err| format!("could not parse \"{s}\": {err}"))?;
+    Ok(d.with_base_and_precision::<2>(prec).value())
+}
+
+fn hp_from_f64(x: f64, prec: usize) -> HPFloat {
+    HPFloat::try_from(x)
+        .unwrap_or(HPFloat::ZERO)
+        .with_precision(prec)
+        .value()
+}
+
+fn format_hp(x: HPFloat, prec: usize) -> String {
+    // ~log10(2) decimal digits per bit
+    let digits = prec * 3 / 10 + 3;
+    x.with_base_and_precision::<10>(digits).value().to_string()
+}
+
+impl DeepZoom {
+    /// Enough bits to resolve a pixel at the given zoom, plus headroom for the orbit
+    pub fn precision_bits(zoom: f64) -> usize {
+        64 + (-zoom.log2()).max(0.0) as usize
+    }
+
+    fn center(&self, prec: usize) -> Result<(HPFloat, HPFloat), String> {
+        Ok((
+            parse_hp(&self.center_re, prec)?,
+            parse_hp(&self.center_im, prec)?,
+        ))
+    }
+
+    /// Double precision approximation of the center, for display and the non perturbed kernels
+    pub fn center_approx(&self) -> Option<Complex> {
+        let (re, im) = self.center(64).ok()?;
+        Some(Complex {
+            re: re.to_f64().value(),
+            im: im.to_f64().value(),
+        })
+    }
+
+    pub fn set_center(&mut self, center: Complex) {
+        self.center_re = format!("{:e}", center.re);
+        self.center_im = format!("{:e}", center.im);
+    }
+
+    /// Moves the center by an offset without losing the digits beyond double precision
+    pub fn translate(&mut self, offset: Complex, zoom: f64) -> Result<(), String> {
+        let prec = Self::precision_bits(zoom);
+        let (re, im) = self.center(prec)?;
+        self.center_re = format_hp(re + hp_from_f64(offset.re, prec), prec);
+        self.center_im = format_hp(im + hp_from_f64(offset.im, prec), prec);
+        Ok(())
+    }
+
+    /// Iterates z^2+c from the view center, in mandel mode starting from the critical point
+    /// (so the pixel orbits are this one shifted by one step)
+    pub fn reference_orbit(
+        &self,
+        julia_c: Option<Complex>,
+        zoom: f64,
+        max_iter: i32,
+    ) -> Result<Vec<Complex>, String> {
+        let prec = Self::precision_bits(zoom);
+        let (center_re, center_im) = self.center(prec)?;
+        let (mut zr, mut zi, cr, ci) = match julia_c {
+            Some(c) => (
+                center_re,
+                center_im,
+                hp_from_f64(c.re, prec),
+                hp_from_f64(c.im, prec),
+            ),
+            None => (
+                hp_from_f64(0.0, prec),
+                hp_from_f64(0.0, prec),
+                center_re,
+                center_im,
+            ),
+        };
+
+        let mut orbit = Vec::with_capacity(max_iter as usize + 2);
+        loop {
+            let z = Complex {
+                re: zr.to_f64().value(),
+                im: zi.to_f64().value(),
+            };
+            orbit.push(z);
+            if orbit.len() > 1
+                && (orbit.len() > max_iter as usize + 1 || z.norm_sqr() > REFERENCE_ESCAPE_NORM)
+            {
+                break;
+            }
+            let zri = &zr * &zi;
+            zr = &zr * &zr - &zi * &zi + &cr;
+            zi = &zri + &zri + &ci;
+        }
+
+        Ok(orbit)
+    }
+}
@@ -0,0 +1,552 @@
+use egui_inspect::logging::log::error;
+use ndarray::{Array2, Array3};
+use ocl::ProQue;
+use simple_ocl::{try_prog_que_from_source, PairedBuffers2, PairedBuffers3};
+use std::path::PathBuf;
+
+use crate::backend::{BackendResult, RenderBackend};
+use crate::buddhabrot::HistogramState;
+use crate::image_io::load_decoded;
+use crate::shape_trap::TrapOutput;
+use crate::wrapper_types::{
+    BBox, BuddhabrotParam, Complex, DistanceParams, Freqs, ImDims, LyapunovParam, NewtonParam,
+    PaletteMapping, ProxType, SFParam, SceneParam, ShapeTrapParam, SmoothParams,
+};
+
+// ocl source baked into binary at build time
+static OCL_STRUCTS: &str = include_str!("./ocl/mandelstructs.h");
+static OCL_FUNCS: &str = include_str!("./ocl/mandelutils.c");
+static OCL_KERNELS: &str = include_str!("./ocl/mandel.cl");
+
+fn insert_custom_func(custom_func: String) -> String {
+    let funcs = OCL_FUNCS.to_string();
+    let mut sp = funcs.split("//>>");
+    let [before_func, remainder] = [sp.next().unwrap(), sp.next().unwrap()];
+    let rs = remainder.to_string();
+    let mut sp = rs.split("//<<");
+    let [_, after_func] = [sp.next().unwrap(), sp.next().unwrap()];
+    format!("{before_func}{custom_func}{after_func}")
+}
+
+/// Fields, histograms and rgb image for images of `im_dims`, with the que sized to match
+fn image_buffers(
+    pro_que: &mut ProQue,
+    im_dims: (usize, usize),
+) -> (
+    [PairedBuffers2<f64>; 3],
+    [PairedBuffers2<i32>; 3],
+    PairedBuffers3<u8>,
+) {
+    let fields = std::array::from_fn(|_| {
+        PairedBuffers2::create_from(Array2::<f64>::zeros(im_dims), pro_que)
+    });
+    let histograms = std::array::from_fn(|_| {
+        PairedBuffers2::create_from(Array2::<i32>::zeros(im_dims), pro_que)
+    });
+    let (n, m) = im_dims;
+    let rgb = PairedBuffers3::create_from(Array3::<u8>::zeros((n, m, 3)), pro_que);
+    pro_que.set_dims(im_dims);
+    (fields, histograms, rgb)
+}
+
+/// OpenCL backend
+pub struct FractalCompute {
+    pro_que: ProQue,
+    dims: (usize, usize),
+    field_1: PairedBuffers2<f64>,
+    field_2: PairedBuffers2<f64>,
+    field_3: PairedBuffers2<f64>,
+    histograms: [PairedBuffers2<i32>; 3],
+    histogram_state: HistogramState,
+    sampled_path: Option<PathBuf>,
+    sampled_rgb: Option<PairedBuffers3<u8>>,
+    rgb: PairedBuffers3<u8>,
+}
+
+impl FractalCompute {
+    pub fn new(im_dims: (usize, usize), custom_iter_func: Option<String>) -> ocl::Result<Self> {
+        let ocl_funcs_custom = match custom_iter_func {
+            Some(cf) => insert_custom_func(cf),
+            None => OCL_FUNCS.to_string(),
+        };
+        let full_source = format!("{OCL_STRUCTS}{ocl_funcs_custom}{OCL_KERNELS}");
+        let mut pro_que =
+            try_prog_que_from_source(full_source, "mandel", vec!["-DEXTERNAL_CONCAT".to_string()])?;
+        let ([field_1, field_2, field_3], histograms, rgb) = image_buffers(&mut pro_que, im_dims);
+        Ok(FractalCompute {
+            pro_que,
+            dims: im_dims,
+            field_1,
+            field_2,
+            field_3,
+            histograms,
+            histogram_state: Default::default(),
+            rgb,
+            sampled_path: None,
+            sampled_rgb: None,
+        })
+    }
+
+    fn field_ref(&self, i: usize) -> &ocl::Buffer<f64> {
+        match i {
+            1 => &self.field_1.device,
+            2 => &self.field_2.device,
+            3 => &self.field_3.device,
+            _ => {
+                panic!("invalid field index");
+            }
+        }
+    }
+}
+
+impl RenderBackend for FractalCompute {
+    fn sampled_path(&self) -> Option<&PathBuf> {
+        self.sampled_path.as_ref()
+    }
+
+    fn has_sampled(&self) -> bool {
+        self.sampled_rgb.is_some()
+    }
+
+    fn update_sampled(&mut self, ip: PathBuf) {
+        match load_decoded(&ip) {
+            Ok(sampled) => {
+                let pb = PairedBuffers3::create_from(sampled, &mut self.pro_que);
+                // create_from changes que size
+                self.pro_que.set_dims(self.dims);
+                pb.to_device().unwrap();
+                self.sampled_rgb = Some(pb);
+                self.sampled_path = Some(ip);
+            }
+            Err(err) => error!("{err}"),
+        }
+    }
+
+    fn resize(&mut self, im_dims: (usize, usize)) {
+        let ([field_1, field_2, field_3], histograms, rgb) =
+            image_buffers(&mut self.pro_que, im_dims);
+        self.field_1 = field_1;
+        self.field_2 = field_2;
+        self.field_3 = field_3;
+        self.histograms = histograms;
+        self.histogram_state = Default::default();
+        self.rgb = rgb;
+        self.dims = im_dims;
+    }
+
+    fn run_escape_iter(&mut self, fi: usize, fparam: SFParam) -> BackendResult<()> {
+        let kernel = self
+            .pro_que
+            .kernel_builder("escape_iter_fpn")
+            .arg(self.field_ref(fi))
+            .arg(fparam)
+            .build()?;
+
+        unsafe {
+            kernel.enq()?;
+        }
+
+        Ok(())
+    }
+
+    fn run_interior_period(&mut self, fi: usize, fparam: SFParam) -> BackendResult<()> {
+        let kernel = self
+            .pro_que
+            .kernel_builder("interior_period")
+            .arg(self.field_ref(fi))
+            .arg(fparam)
+            .build()?;
+
+        unsafe {
+            kernel.enq()?;
+        }
+
+        Ok(())
+    }
+
+    fn run_smooth_escape_iter(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        smooth: SmoothParams,
+    ) -> BackendResult<()> {
+        let kernel = self
+            .pro_que
+            .kernel_builder("smooth_escape_iter")
+            .arg(self.field_ref(fi))
+            .arg(fparam)
+            .arg(smooth)
+            .build()?;
+
+        unsafe {
+            kernel.enq()?;
+        }
+
+        Ok(())
+    }
+
+    fn run_perturbed_escape_iter(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        reference: &[Complex],
+        smooth: Option<SmoothParams>,
+    ) -> BackendResult<()> {
+        let ref_host = Array2::from_shape_fn((reference.len(), 2), |(k, l)| {
+            if l == 0 {
+                reference[k].re
+            } else {
+                reference[k].im
+            }
+        });
+        let ref_pb = PairedBuffers2::create_from(ref_host, &mut self.pro_que);
+        // create_from changes que size
+        self.pro_que.set_dims(self.dims);
+        ref_pb.to_device()?;
+
+        let kernel = self
+            .pro_que
+            .kernel_builder("perturbed_escape_iter")
+            .arg(self.field_ref(fi))
+            .arg(fparam)
+            .arg(&ref_pb.device)
+            .arg(reference.len() as i32)
+            .arg(smooth.unwrap_or_default())
+            .arg(smooth.is_some() as i32)
+            .build()?;
+
+        unsafe {
+            kernel.enq()?;
+        }
+
+        Ok(())
+    }
+
+    fn run_distance_estimate(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        distance: DistanceParams,
+    ) -> BackendResult<()> {
+        let kernel = self
+            .pro_que
+            .kernel_builder("distance_estimate")
+            .arg(self.field_ref(fi))
+            .arg(fparam)
+            .arg(distance)
+            .build()?;
+
+        unsafe {
+            kernel.enq()?;
+        }
+
+        Ok(())
+    }
+
+    fn run_min_prox(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        prox_type: ProxType,
+    ) -> BackendResult<()> {
+        let kernel = self
+            .pro_que
+            .kernel_builder("min_prox")
+            .arg(self.field_ref(fi))
+            .arg(fparam)
+            .arg(prox_type)
+            .build()?;
+
+        unsafe {
+            kernel.enq()?;
+        }
+
+        Ok(())
+    }
+
+    fn run_box_trap_partial(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        box_: BBox,
+        real: bool,
+    ) -> BackendResult<()> {
+        let kernel_name = if real {
+            "orbit_trap_re"
+        } else {
+            "orbit_trap_im"
+        };
+
+        let kernel = self
+            .pro_que
+            .kernel_builder(kernel_name)
+            .arg(self.field_ref(fi))
+            .arg(fparam)
+            .arg(box_)
+            .build()?;
+
+        unsafe {
+            kernel.enq()?;
+        }
+
+        Ok(())
+    }
+
+    fn run_shape_trap(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        trap: ShapeTrapParam,
+        output: TrapOutput,
+    ) -> BackendResult<()> {
+        let kernel = self
+            .pro_que
+            .kernel_builder("shape_trap")
+            .arg(self.field_ref(fi))
+            .arg(fparam)
+            .arg(trap)
+            .arg(output as i32)
+            .build()?;
+
+        unsafe {
+            kernel.enq()?;
+        }
+
+        Ok(())
+    }
+
+    fn run_newton(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        newton: NewtonParam,
+        root: bool,
+    ) -> BackendResult<()> {
+        let kernel_name = if root { "newton_root" } else { "newton_iters" };
+
+        let kernel = self
+            .pro_que
+            .kernel_builder(kernel_name)
+            .arg(self.field_ref(fi))
+            .arg(fparam)
+            .arg(newton)
+            .build()?;
+
+        unsafe {
+            kernel.enq()?;
+        }
+
+        Ok(())
+    }
+
+    fn run_lyapunov(
+        &mut self,
+        fi: usize,
+        fparam: SFParam,
+        lyapunov: LyapunovParam,
+    ) -> BackendResult<()> {
+        let kernel = self
+            .pro_que
+            .kernel_builder("lyapunov")
+            .arg(self.field_ref(fi))
+            .arg(fparam)
+            .arg(lyapunov)
+            .build()?;
+
+        unsafe {
+            kernel.enq()?;
+        }
+
+        Ok(())
+    }
+
+    fn run_buddhabrot_pass(
+        &mut self,
+        fparam: SFParam,
+        buddha: BuddhabrotParam,
+    ) -> BackendResult<()> {
+        let n_samples = self.dims.0 * self.dims.1 * buddha.samples as usize;
+        if self.histogram_state.begin_pass(fparam, buddha, n_samples) {
+            for hist in self.histograms.iter_mut() {
+                hist.host.fill(0);
+                hist.to_device()?;
+            }
+        }
+
+        let kernel = self
+            .pro_que
+            .kernel_builder("buddhabrot")
+            .arg(&self.histograms[0].device)
+            .arg(&self.histograms[1].device)
+            .arg(&self.histograms[2].device)
+            .arg(fparam)
+            .arg(buddha)
+            .arg(self.histogram_state.passes as u32)
+            .build()?;
+
+        unsafe {
+            kernel.enq()?;
+        }
+
+        Ok(())
+    }
+
+    fn histogram_state(&self) -> &HistogramState {
+        &self.histogram_state
+    }
+
+    fn run_map_histograms(&mut self, exposure: f64) -> BackendResult<()> {
+        let scales = self.histogram_state.density_scales(self.dims, exposure);
+        for (k, (hist, scale)) in self.histograms.iter().zip(scales).enumerate() {
+            let kernel = self
+                .pro_que
+                .kernel_builder("map_histogram")
+                .arg(&hist.device)
+                .arg(self.field_ref(k + 1))
+                .arg(scale)
+                .build()?;
+
+            unsafe {
+                kernel.enq()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn run_ray_march(&mut self, fparam: SFParam, scene: SceneParam) -> BackendResult<()> {
+        let kernel = self
+            .pro_que
+            .kernel_builder("ray_march")
+            .arg(&self.rgb.device)
+            .arg(fparam)
+            .arg(scene)
+            .build()?;
+
+        unsafe {
+            kernel.enq()?;
+        }
+
+        Ok(())
+    }
+
+    fn run_map_sines(&mut self, freqs: Freqs) -> BackendResult<()> {
+        let kernel = self
+            .pro_que
+            .kernel_builder("map_sines")
+            .arg(&self.field_1.device)
+            .arg(&self.rgb.device)
+            .arg(freqs)
+            .build()?;
+
+        unsafe {
+            kernel.enq()?;
+        }
+
+        Ok(())
+    }
+
+    fn run_map_palette(&mut self, lut: &Array2<u8>, mapping: PaletteMapping) -> BackendResult<()> {
+        let lut_pb = PairedBuffers2::create_from(lut.clone(), &mut self.pro_que);
+        // create_from changes que size
+        self.pro_que.set_dims(self.dims);
+        lut_pb.to_device()?;
+
+        let kernel = self
+            .pro_que
+            .kernel_builder("map_palette")
+            .arg(&self.field_1.device)
+            .arg(&lut_pb.device)
+            .arg(lut.nrows() as i32)
+            .arg(mapping)
+            .arg(&self.rgb.device)
+            .build()?;
+
+        unsafe {
+            kernel.enq()?;
+        }
+
+        Ok(())
+    }
+
+    fn run_map_basins(&mut self, colors: &Array2<u8>, shading: f64) -> BackendResult<()> {
+        let colors_pb = PairedBuffers2::create_from(colors.clone(), &mut self.pro_que);
+        // create_from changes que size
+        self.pro_que.set_dims(self.dims);
+        colors_pb.to_device()?;
+
+        let kernel = self
+            .pro_que
+            .kernel_builder("map_basins")
+            .arg(&self.field_1.device)
+            .arg(&self.field_2.device)
+            .arg(&colors_pb.device)
+            .arg(colors.nrows() as i32)
+            .arg(shading)
+            .arg(&self.rgb.device)
+            .build()?;
+
+        unsafe {
+            kernel.enq()?;
+        }
+
+        Ok(())
+    }
+
+    fn run_pack(&mut self, normalise: bool) -> BackendResult<()> {
+        let kernel_name = if normalise { "pack_norm" } else { "pack" };
+        let kernel = self
+            .pro_que
+            .kernel_builder(kernel_name)
+            .arg(&self.field_1.device)
+            .arg(&self.field_2.device)
+            .arg(&self.field_3.device)
+            .arg(&self.rgb.device)
+            .build()?;
+
+        unsafe {
+            kernel.enq()?;
+        }
+
+        Ok(())
+    }
+
+    fn run_map_img(&mut self, bilinear: bool) -> BackendResult<()> {
+        if let Some(sampled) = self.sampled_rgb.as_ref() {
+            let s = sampled.host.shape();
+            let imdims = ImDims {
+                height: s[0] as i32,
+                width: s[1] as i32,
+            };
+            let kernel_name = if bilinear { "map_img3" } else { "map_img2" };
+            let kernel = self
+                .pro_que
+                .kernel_builder(kernel_name)
+                .arg(&self.field_1.device)
+                .arg(&self.field_2.device)
+                .arg(&sampled.device)
+                .arg(&self.rgb.device)
+                .arg(imdims)
+                .build()?;
+
+            unsafe {
+                kernel.enq()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_rgb(&mut self) -> BackendResult<()> {
+        self.rgb.from_device()?;
+        Ok(())
+    }
+
+    fn rgb(&self) -> &Array3<u8> {
+        &self.rgb.host
+    }
+
+    fn rgb_mut(&mut self) -> &mut Array3<u8> {
+        &mut self.rgb.host
+    }
+}
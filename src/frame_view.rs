@@ -1,19 +1,42 @@
 // TODO: Should be editing the image data of the texturehandle on the gpu?
 
-use egui_inspect::egui::{self, ColorImage, Image, TextureHandle};
+use egui_inspect::egui::{
+    self, Color32, ColorImage, Image, Pos2, Rect, Sense, Stroke, TextureHandle, Vec2,
+};
 use egui_inspect::EguiInspect;
 use ndarray::Array3;
 
+/// View change requested through the mouse, positions are fractions of the image size with
+/// the origin at the top left
+pub enum Navigation {
+    /// drag, moving the content along with the cursor
+    Pan(Vec2),
+    /// scroll, factors above 1 zoom in while keeping the point under the cursor fixed
+    ZoomAbout { at: Pos2, factor: f64 },
+    /// shift-drag, fit the selected rectangle to the view
+    ZoomToRect(Rect),
+    /// double click
+    Recenter(Pos2),
+}
+
 pub struct FrameView {
     pub dims: (usize, usize),
+    /// cursor position over the frame during the last `show`, as a fraction of its size
+    pub hovered: Option<Pos2>,
+    /// likewise for a single click
+    pub clicked: Option<Pos2>,
     texture: Option<TextureHandle>,
+    rubber_band_start: Option<Pos2>,
 }
 
 impl FrameView {
     pub fn new(dims: (usize, usize)) -> Self {
         Self {
             dims,
+            hovered: None,
+            clicked: None,
             texture: None,
+            rubber_band_start: None,
         }
     }
 
@@ -23,14 +46,9 @@ impl FrameView {
             handle.set(cimage, Default::default());
         }
     }
-}
-
-impl EguiInspect for FrameView {
-    fn inspect(&self, _label: &str, _ui: &mut egui::Ui) {
-        todo!()
-    }
 
-    fn inspect_mut(&mut self, _label: &str, ui: &mut egui::Ui) {
+    /// Draws the frame and turns mouse interaction on it into navigation requests
+    pub fn show(&mut self, ui: &mut egui::Ui) -> Option<Navigation> {
         let handle: &egui::TextureHandle = self.texture.get_or_insert_with(|| {
             let cimage = ColorImage::from_rgb(
                 [self.dims.1, self.dims.0],
@@ -39,6 +57,72 @@ impl EguiInspect for FrameView {
             ui.ctx()
                 .load_texture("fractal", cimage.clone(), Default::default())
         });
-        ui.add(Image::new(handle).shrink_to_fit());
+        let response = ui.add(
+            Image::new(handle)
+                .shrink_to_fit()
+                .sense(Sense::click_and_drag()),
+        );
+
+        let rect = response.rect;
+        let to_frac = |pos: Pos2| ((pos - rect.min) / rect.size()).to_pos2();
+        let shift = ui.input(|i| i.modifiers.shift);
+        self.hovered = response.hover_pos().map(to_frac);
+        self.clicked = response
+            .clicked()
+            .then(|| response.interact_pointer_pos().map(to_frac))
+            .flatten();
+
+        if response.double_clicked() {
+            return response
+                .interact_pointer_pos()
+                .map(|pos| Navigation::Recenter(to_frac(pos)));
+        }
+
+        if response.drag_started() && shift {
+            self.rubber_band_start = response.interact_pointer_pos();
+        }
+        if let Some(start) = self.rubber_band_start {
+            let current = response.interact_pointer_pos().unwrap_or(start);
+            let band = Rect::from_two_pos(start, current);
+            if response.drag_stopped() {
+                self.rubber_band_start = None;
+                return Some(Navigation::ZoomToRect(Rect::from_two_pos(
+                    to_frac(band.min),
+                    to_frac(band.max),
+                )));
+            }
+            ui.painter()
+                .rect_stroke(band, 0.0, Stroke::new(1.0, Color32::WHITE));
+            return None;
+        }
+
+        if response.dragged() {
+            let delta = response.drag_delta();
+            if delta != Vec2::ZERO {
+                return Some(Navigation::Pan(delta / rect.size()));
+            }
+        }
+
+        if let Some(pos) = response.hover_pos() {
+            let scroll = ui.input(|i| i.smooth_scroll_delta.y);
+            if scroll != 0.0 {
+                return Some(Navigation::ZoomAbout {
+                    at: to_frac(pos),
+                    factor: (scroll as f64 * 0.005).exp(),
+                });
+            }
+        }
+
+        None
+    }
+}
+
+impl EguiInspect for FrameView {
+    fn inspect(&self, _label: &str, _ui: &mut egui::Ui) {
+        todo!()
+    }
+
+    fn inspect_mut(&mut self, _label: &str, ui: &mut egui::Ui) {
+        self.show(ui);
     }
 }
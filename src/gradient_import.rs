@@ -0,0 +1,416 @@
+//! Parsers for external gradient formats, each turned into palette stops:
+//! GIMP `.ggr`, Fractint/Ultra Fractal `.map` and cpt-city/GMT `.cpt`.
+
+use std::f64::consts::PI;
+use std::path::Path;
+
+use crate::palette::{ColorStop, Interpolation, Palette};
+
+/// Samples taken per GIMP gradient segment, enough to follow its blending curve
+static GGR_SAMPLES_PER_SEGMENT: usize = 16;
+
+fn to_u8(v: f64) -> u8 {
+    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+fn parse_f64(s: &str, what: &str) -> Result<f64, String> {
+    s.parse::<f64>()
+        .map_err(|err| format!("invalid {what} \"{s}\": {err}"))
+}
+
+fn rgb_to_hsv([r, g, b]: [f64; 3]) -> [f64; 3] {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let d = max - min;
+    let h = if d == 0.0 {
+        0.0
+    } else if max == r {
+        ((g - b) / d).rem_euclid(6.0) / 6.0
+    } else if max == g {
+        ((b - r) / d + 2.0) / 6.0
+    } else {
+        ((r - g) / d + 4.0) / 6.0
+    };
+    let s = if max == 0.0 { 0.0 } else { d / max };
+    [h, s, max]
+}
+
+fn hsv_to_rgb([h, s, v]: [f64; 3]) -> [f64; 3] {
+    let h6 = h.rem_euclid(1.0) * 6.0;
+    let c = v * s;
+    let x = c * (1.0 - (h6 % 2.0 - 1.0).abs());
+    let (r, g, b) = match h6 as usize {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = v - c;
+    [r + m, g + m, b + m]
+}
+
+/// One segment of a GIMP gradient, positions in [0, 1] and colours in float rgb
+struct GgrSegment {
+    left: f64,
+    middle: f64,
+    right: f64,
+    left_color: [f64; 3],
+    right_color: [f64; 3],
+    blend: u32,
+    coloring: u32,
+}
+
+impl GgrSegment {
+    fn parse(line: &str) -> Result<Self, String> {
+        let values = line
+            .split_whitespace()
+            .map(|v| parse_f64(v, "segment value"))
+            .collect::<Result<Vec<_>, _>>()?;
+        if values.len() < 13 {
+            return Err(format!("segment with too few values: \"{line}\""));
+        }
+        Ok(Self {
+            left: values[0],
+            middle: values[1],
+            right: values[2],
+            // alpha (values 6 and 10) is not used by the colouring
+            left_color: [values[3], values[4], values[5]],
+            right_color: [values[7], values[8], values[9]],
+            blend: values[11] as u32,
+            coloring: values[12] as u32,
+        })
+    }
+
+    /// Blend factor at position `t` in [0, 1] through the segment, as in GIMP's gradient.c
+    fn factor(&self, t: f64) -> f64 {
+        let len = (self.right - self.left).max(f64::EPSILON);
+        let mid = ((self.middle - self.left) / len).clamp(1e-6, 1.0 - 1e-6);
+        let linear = if t <= mid {
+            0.5 * t / mid
+        } else {
+            0.5 + 0.5 * (t - mid) / (1.0 - mid)
+        };
+        match self.blend {
+            1 => t.powf(0.5f64.ln() / mid.ln()),
+            2 => ((-PI / 2.0 + PI * linear).sin() + 1.0) / 2.0,
+            3 => (1.0 - (linear - 1.0).powi(2)).sqrt(),
+            4 => 1.0 - (1.0 - linear.powi(2)).sqrt(),
+            5 => (t >= mid) as u32 as f64,
+            _ => linear,
+        }
+    }
+
+    fn color(&self, t: f64) -> [u8; 3] {
+        let f = self.factor(t);
+        let rgb = match self.coloring {
+            // hsv, counter clockwise and clockwise around the hue circle
+            1 | 2 => {
+                let [h0, s0, v0] = rgb_to_hsv(self.left_color);
+                let [mut h1, s1, v1] = rgb_to_hsv(self.right_color);
+                if self.coloring == 1 && h1 < h0 {
+                    h1 += 1.0;
+                } else if self.coloring == 2 && h1 > h0 {
+                    h1 -= 1.0;
+                }
+                hsv_to_rgb([h0 + f * (h1 - h0), s0 + f * (s1 - s0), v0 + f * (v1 - v0)])
+            }
+            _ => [0, 1, 2]
+                .map(|k| self.left_color[k] + f * (self.right_color[k] - self.left_color[k])),
+        };
+        rgb.map(to_u8)
+    }
+
+    fn stops(&self) -> Vec<ColorStop> {
+        let len = self.right - self.left;
+        if self.blend == 5 {
+            // step, a hard edge at the middle
+            let left = self.color(0.0);
+            let right = self.color(1.0);
+            return vec![
+                ColorStop {
+                    pos: self.left,
+                    color: left,
+                },
+                ColorStop {
+                    pos: self.middle,
+                    color: left,
+                },
+                ColorStop {
+                    pos: self.middle,
+                    color: right,
+                },
+                ColorStop {
+                    pos: self.right,
+                    color: right,
+                },
+            ];
+        }
+        (0..=GGR_SAMPLES_PER_SEGMENT)
+            .map(|k| {
+                let t = k as f64 / GGR_SAMPLES_PER_SEGMENT as f64;
+                ColorStop {
+                    pos: self.left + t * len,
+                    color: self.color(t),
+                }
+            })
+            .collect()
+    }
+}
+
+pub fn parse_ggr(text: &str) -> Result<Vec<ColorStop>, String> {
+    let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
+    if lines.next() != Some("GIMP Gradient") {
+        return Err("not a GIMP gradient, missing \"GIMP Gradient\" header".to_string());
+    }
+    let mut line = lines.next().ok_or("missing segment count")?;
+    if line.starts_with("Name:") {
+        line = lines.next().ok_or("missing segment count")?;
+    }
+    let n_segments = line
+        .parse::<usize>()
+        .map_err(|err| format!("invalid segment count \"{line}\": {err}"))?;
+
+    let mut stops = vec![];
+    for line in lines.take(n_segments) {
+        stops.extend(GgrSegment::parse(line)?.stops());
+    }
+    if stops.is_empty() {
+        return Err("gradient has no segments".to_string());
+    }
+    Ok(stops)
+}
+
+/// Fractint style palettes, "r g b" per line (0 to 255) for (usually) 256 entries, anything
+/// after the third value being a comment
+pub fn parse_map(text: &str) -> Result<Vec<ColorStop>, String> {
+    let mut colors = vec![];
+    for line in text.lines() {
+        let values: Vec<_> = line.split_whitespace().take(3).collect();
+        if values.len() < 3 {
+            continue;
+        }
+        let mut color = [0; 3];
+        for (c, v) in color.iter_mut().zip(values) {
+            *c = v
+                .parse::<u8>()
+                .map_err(|err| format!("invalid colour value \"{v}\": {err}"))?;
+        }
+        colors.push(color);
+    }
+    if colors.len() < 2 {
+        return Err("palette needs at least two entries".to_string());
+    }
+    let last = (colors.len() - 1) as f64;
+    Ok(colors
+        .into_iter()
+        .enumerate()
+        .map(|(k, color)| ColorStop {
+            pos: k as f64 / last,
+            color,
+        })
+        .collect())
+}
+
+/// Colour of a cpt slice boundary, either "r g b", "r/g/b" or a single grey level, in the file's
+/// colour model
+fn parse_cpt_color(values: &[&str], hsv: bool) -> Result<[u8; 3], String> {
+    let parts: Vec<&str> = match values {
+        [single] => single.split('/').collect(),
+        many => many.to_vec(),
+    };
+    let nums = parts
+        .iter()
+        .map(|v| parse_f64(v, "colour value"))
+        .collect::<Result<Vec<_>, _>>()?;
+    match nums.as_slice() {
+        [grey] => Ok([to_u8(grey / 255.0); 3]),
+        [h, s, v] if hsv => Ok(hsv_to_rgb([h / 360.0, *s, *v]).map(to_u8)),
+        [r, g, b] => Ok([r, g, b].map(|c| to_u8(c / 255.0))),
+        _ => Err(format!("unsupported colour \"{}\"", values.join(" "))),
+    }
+}
+
+/// cpt-city/GMT colour tables, "z0 colour0 z1 colour1" per slice, positions are normalised to
+/// the table's range
+pub fn parse_cpt(text: &str) -> Result<Vec<ColorStop>, String> {
+    let mut hsv = false;
+    let mut stops = vec![];
+    for line in text.lines().map(str::trim) {
+        if let Some(comment) = line.strip_prefix('#') {
+            if comment
+                .replace(' ', "")
+                .eq_ignore_ascii_case("COLOR_MODEL=HSV")
+            {
+                hsv = true;
+            }
+            continue;
+        }
+        // annotations follow a ';'
+        let line = line.split(';').next().unwrap_or_default();
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        // background, foreground and NaN colours
+        if fields.is_empty() || matches!(fields[0], "B" | "F" | "N") {
+            continue;
+        }
+        // both colours take as many fields, the 'L/U/B' label column is ignored
+        let n_color = match fields.len() {
+            4 | 5 => 1,
+            8 | 9 => 3,
+            _ => return Err(format!("unsupported cpt line \"{line}\"")),
+        };
+        let z0 = parse_f64(fields[0], "position")?;
+        let z1 = parse_f64(fields[1 + n_color], "position")?;
+        stops.push(ColorStop {
+            pos: z0,
+            color: parse_cpt_color(&fields[1..1 + n_color], hsv)?,
+        });
+        stops.push(ColorStop {
+            pos: z1,
+            color: parse_cpt_color(&fields[2 + n_color..2 + 2 * n_color], hsv)?,
+        });
+    }
+
+    let (zmin, zmax) = match (stops.first(), stops.last()) {
+        (Some(first), Some(last)) if last.pos > first.pos => (first.pos, last.pos),
+        _ => return Err("colour table has no slices".to_string()),
+    };
+    for stop in stops.iter_mut() {
+        stop.pos = (stop.pos - zmin) / (zmax - zmin);
+    }
+    Ok(stops)
+}
+
+/// Loads a gradient file by extension, blended in plain rgb as the source applications do
+pub fn load_gradient(fpath: impl AsRef<Path>) -> Result<Palette, String> {
+    let fpath = fpath.as_ref();
+    let text = std::fs::read_to_string(fpath)
+        .map_err(|err| format!("could not read {}: {err}", fpath.display()))?;
+    let ext = fpath
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let stops = match ext.as_str() {
+        "ggr" => parse_ggr(&text)?,
+        "map" => parse_map(&text)?,
+        "cpt" => parse_cpt(&text)?,
+        _ => return Err(format!("unknown gradient format \"{ext}\"")),
+    };
+    Ok(Palette::from_stops(stops, Interpolation::Rgb))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stop(pos: f64, color: [u8; 3]) -> ColorStop {
+        ColorStop { pos, color }
+    }
+
+    #[test]
+    fn ggr_linear_and_step_segments() {
+        let text = "GIMP Gradient
+Name: test
+2
+0 0.25 0.5 0 0 0 1 1 1 1 1 0 0
+0.5 0.75 1 1 0 0 1 0 0 1 1 5 0
+";
+        let stops = parse_ggr(text).unwrap();
+        assert_eq!(stops.len(), GGR_SAMPLES_PER_SEGMENT + 1 + 4);
+        assert_eq!(stops[0], stop(0.0, [0, 0, 0]));
+        assert_eq!(
+            stops[GGR_SAMPLES_PER_SEGMENT / 2],
+            stop(0.25, [128, 128, 128])
+        );
+        assert_eq!(stops[GGR_SAMPLES_PER_SEGMENT], stop(0.5, [255, 255, 255]));
+        assert_eq!(
+            stops[GGR_SAMPLES_PER_SEGMENT + 1..],
+            [
+                stop(0.5, [255, 0, 0]),
+                stop(0.75, [255, 0, 0]),
+                stop(0.75, [0, 0, 255]),
+                stop(1.0, [0, 0, 255]),
+            ]
+        );
+    }
+
+    #[test]
+    fn ggr_hsv_goes_around_the_hue_circle() {
+        // red to green counter clockwise passes through yellow rather than grey
+        let text = "GIMP Gradient\n1\n0 0.5 1 1 0 0 1 0 1 0 1 0 1\n";
+        let stops = parse_ggr(text).unwrap();
+        assert_eq!(stops[GGR_SAMPLES_PER_SEGMENT / 2].color, [255, 255, 0]);
+        assert_eq!(stops[GGR_SAMPLES_PER_SEGMENT].color, [0, 255, 0]);
+    }
+
+    #[test]
+    fn ggr_malformed() {
+        assert!(parse_ggr("0 0.5 1 0 0 0 1 1 1 1 1 0 0\n").is_err());
+        assert!(parse_ggr("GIMP Gradient\n1\n0 0.5 1 0 0 0\n").is_err());
+        assert!(parse_ggr("GIMP Gradient\n").is_err());
+    }
+
+    #[test]
+    fn map_entries_spread_evenly() {
+        let text = "0 0 0 black\n255 0 0\n\n255 255 255 white\n";
+        assert_eq!(
+            parse_map(text).unwrap(),
+            [
+                stop(0.0, [0, 0, 0]),
+                stop(0.5, [255, 0, 0]),
+                stop(1.0, [255, 255, 255]),
+            ]
+        );
+    }
+
+    #[test]
+    fn map_malformed() {
+        assert!(parse_map("0 0 0\n").is_err());
+        assert!(parse_map("0 0 0\n256 0 0\n").is_err());
+    }
+
+    #[test]
+    fn cpt_rgb_normalised_to_z_range() {
+        let text = "# COLOR_MODEL = RGB
+-1 0/0/0 0 255/0/0 ; annotation
+0 255 0 0 1 255 255 255 L
+B 0 0 0
+F 255 255 255
+N 128 128 128
+";
+        assert_eq!(
+            parse_cpt(text).unwrap(),
+            [
+                stop(0.0, [0, 0, 0]),
+                stop(0.5, [255, 0, 0]),
+                stop(0.5, [255, 0, 0]),
+                stop(1.0, [255, 255, 255]),
+            ]
+        );
+    }
+
+    #[test]
+    fn cpt_hsv_and_grey() {
+        let hsv = "# COLOR_MODEL = HSV\n0 0 1 1 1 120 1 1\n";
+        assert_eq!(
+            parse_cpt(hsv).unwrap(),
+            [stop(0.0, [255, 0, 0]), stop(1.0, [0, 255, 0])]
+        );
+        let grey = "10 0 20 255\n";
+        assert_eq!(
+            parse_cpt(grey).unwrap(),
+            [stop(0.0, [0, 0, 0]), stop(1.0, [255, 255, 255])]
+        );
+    }
+
+    #[test]
+    fn cpt_malformed() {
+        // a single z value has no range to normalise by
+        assert!(parse_cpt("0 255 0 0 0 255 0 0\n").is_err());
+        assert!(parse_cpt("# only comments\nB 0 0 0\n").is_err());
+        assert!(parse_cpt("0 255 0 0 1 255\n").is_err());
+        assert!(parse_cpt("0 x 0 0 1 255 0 0\n").is_err());
+    }
+}
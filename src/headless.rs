@@ -0,0 +1,574 @@
+//! `render` subcommand, renders a preset and/or command line parameters straight to a file
+//! without opening a window, e.g.
+//!
+//! `egui-opencl-fractals render --preset deep.ron --size 3840x2160 -o deep.png`
+
+use clap::{Parser, ValueEnum};
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::backend::{create_backend, BackendKind};
+use crate::gradient_import::load_gradient;
+use crate::preset::Preset;
+use crate::scene::Shape;
+use crate::tiled_export::render_tiled;
+use crate::wrapper_types::{Complex, Freqs};
+use crate::{
+    render_fractal, BailoutNorm, Formula, FractalFieldType, FractalMode, FractalParams,
+    FractalVisualisationType, FunctionEditor, SelectedImage, INITIAL_IM_MAT_DIMS,
+};
+
+/// Field types selectable from the command line, with their default settings
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum FieldKind {
+    Iters,
+    Smooth,
+    Distance,
+    MinProx,
+    BoxRe,
+    BoxIm,
+    NewtonRoot,
+    NewtonIters,
+    Lyapunov,
+    Period,
+    ShapeTrap,
+    TrapHitRe,
+    TrapHitIm,
+}
+
+impl From<FieldKind> for FractalFieldType {
+    fn from(kind: FieldKind) -> Self {
+        match kind {
+            FieldKind::Iters => FractalFieldType::ItersToEscape,
+            FieldKind::Smooth => FractalFieldType::SmoothItersToEscape {
+                smooth: Default::default(),
+            },
+            FieldKind::Distance => FractalFieldType::DistanceEstimate {
+                distance: Default::default(),
+            },
+            FieldKind::MinProx => FractalFieldType::ChainMinProximity {
+                prox_type: Default::default(),
+            },
+            FieldKind::BoxRe => FractalFieldType::BoxTrapRe {
+                box_: Default::default(),
+            },
+            FieldKind::BoxIm => FractalFieldType::BoxTrapIm {
+                box_: Default::default(),
+            },
+            FieldKind::NewtonRoot => FractalFieldType::NewtonRoot {
+                newton: Default::default(),
+            },
+            FieldKind::NewtonIters => FractalFieldType::NewtonIters {
+                newton: Default::default(),
+            },
+            FieldKind::Lyapunov => FractalFieldType::Lyapunov {
+                lyapunov: Default::default(),
+            },
+            FieldKind::Period => FractalFieldType::InteriorPeriod,
+            FieldKind::ShapeTrap => FractalFieldType::ShapeTrapDistance {
+                trap: Default::default(),
+            },
+            FieldKind::TrapHitRe => FractalFieldType::ShapeTrapHitRe {
+                trap: Default::default(),
+            },
+            FieldKind::TrapHitIm => FractalFieldType::ShapeTrapHitIm {
+                trap: Default::default(),
+            },
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ModeKind {
+    Mandel,
+    Julia,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum NormKind {
+    Euclidean,
+    Square,
+    Real,
+    Manhattan,
+    Custom,
+}
+
+impl From<NormKind> for BailoutNorm {
+    fn from(kind: NormKind) -> Self {
+        match kind {
+            NormKind::Euclidean => BailoutNorm::Euclidean,
+            NormKind::Square => BailoutNorm::Square,
+            NormKind::Real => BailoutNorm::Real,
+            NormKind::Manhattan => BailoutNorm::Manhattan,
+            NormKind::Custom => BailoutNorm::Custom,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum FormulaKind {
+    Custom,
+    Mandelbrot,
+    BurningShip,
+    Tricorn,
+    Multibrot,
+    Celtic,
+    Buffalo,
+}
+
+impl From<FormulaKind> for Formula {
+    fn from(kind: FormulaKind) -> Self {
+        match kind {
+            FormulaKind::Custom => Formula::CustomFunction,
+            FormulaKind::Mandelbrot => Formula::Mandelbrot,
+            FormulaKind::BurningShip => Formula::BurningShip { flip: false },
+            FormulaKind::Tricorn => Formula::Tricorn,
+            FormulaKind::Multibrot => Formula::Multibrot {
+                multibrot: Default::default(),
+            },
+            FormulaKind::Celtic => Formula::Celtic,
+            FormulaKind::Buffalo => Formula::Buffalo,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ShapeKind {
+    Mandelbulb,
+    Mandelbox,
+}
+
+impl From<ShapeKind> for Shape {
+    fn from(kind: ShapeKind) -> Self {
+        match kind {
+            ShapeKind::Mandelbulb => Shape::Mandelbulb {
+                bulb: Default::default(),
+            },
+            ShapeKind::Mandelbox => Shape::Mandelbox {
+                mandelbox: Default::default(),
+            },
+        }
+    }
+}
+
+/// Render a fractal to an image file without the GUI. Flags override the preset, if given.
+#[derive(Parser, Debug)]
+#[command(name = "render")]
+pub struct RenderArgs {
+    /// Output image, pngs also carry the preset they were rendered from
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Preset to start from, either a .ron preset or a png exported with one (the default
+    /// without an extension, as for the output)
+    #[arg(short, long)]
+    preset: Option<PathBuf>,
+    #[arg(long)]
+    mode: Option<ModeKind>,
+    /// Julia constant as RE,IM
+    #[arg(long, value_parser = parse_complex, allow_hyphen_values = true)]
+    julia_c: Option<Complex>,
+    /// Built in iteration formula, `custom` being the function editor's (or --function's) `f`
+    #[arg(long)]
+    formula: Option<FormulaKind>,
+    /// Exponent of the multibrot formula
+    #[arg(long)]
+    power: Option<f64>,
+    /// Mirror the burning ship vertically
+    #[arg(long)]
+    flip: bool,
+    /// View center as RE,IM, kept at full precision with --deep-zoom
+    #[arg(long, allow_hyphen_values = true)]
+    center: Option<String>,
+    /// Render with a high precision center and perturbation
+    #[arg(long)]
+    deep_zoom: bool,
+    /// Half width of the view
+    #[arg(long)]
+    zoom: Option<f64>,
+    /// Height over width of the view, stretching it rather than following the image size
+    #[arg(long)]
+    aspect: Option<f64>,
+    #[arg(long)]
+    max_iter: Option<i32>,
+    /// Escape radius of the escape time, min prox and buddhabrot orbits
+    #[arg(long)]
+    bailout: Option<f64>,
+    /// Norm compared against --bailout, custom uses `bailout` from --function
+    #[arg(long, value_enum)]
+    norm: Option<NormKind>,
+    /// One field for a colour mapped render, two for an image map (with
+    /// --sampled-image) or three for an rgb render
+    #[arg(
+        long,
+        value_delimiter = ',',
+        num_args = 1..=3,
+        conflicts_with_all = ["basins", "buddhabrot", "ray_march"]
+    )]
+    fields: Vec<FieldKind>,
+    /// "AB" rate sequence of lyapunov fields, e.g. AABAB
+    #[arg(long)]
+    sequence: Option<String>,
+    /// Newton fractal coloured by root basin and shaded by convergence speed
+    #[arg(long)]
+    basins: bool,
+    /// Real polynomial coefficients for --basins, highest degree first, e.g. 1,0,0,-1 for z^3-1
+    #[arg(
+        long,
+        value_delimiter = ',',
+        allow_hyphen_values = true,
+        requires = "basins"
+    )]
+    poly: Vec<f64>,
+    /// Use the nova variant of --basins, adding c after each newton step
+    #[arg(long, requires = "basins")]
+    nova: bool,
+    /// Density of escaping orbits, red green and blue from decreasing iteration limits
+    #[arg(long, conflicts_with = "basins")]
+    buddhabrot: bool,
+    /// Density of the bounded orbits instead, with --buddhabrot
+    #[arg(long, requires = "buddhabrot")]
+    anti: bool,
+    /// Histogram passes accumulated, with --buddhabrot
+    #[arg(long, requires = "buddhabrot")]
+    passes: Option<i32>,
+    /// Ray marched 3D render of a mandelbulb or mandelbox
+    #[arg(long, value_enum, conflicts_with_all = ["basins", "buddhabrot"])]
+    ray_march: Option<ShapeKind>,
+    /// Orbiting camera of --ray-march as YAW,PITCH,DISTANCE, angles in degrees
+    #[arg(long, value_parser = parse_floats::<3>, allow_hyphen_values = true)]
+    camera: Option<[f64; 3]>,
+    /// Colour map frequencies as R,G,B
+    #[arg(long, value_parser = parse_freqs)]
+    freqs: Option<Freqs>,
+    /// Colour a single field render with a gradient file (.ggr, .map or .cpt)
+    #[arg(long)]
+    gradient: Option<PathBuf>,
+    /// Image sampled by two field renders
+    #[arg(long)]
+    sampled_image: Option<PathBuf>,
+    /// Samples per pixel along each axis, averaged in linear light
+    #[arg(long)]
+    supersampling: Option<i32>,
+    /// Image size as WIDTHxHEIGHT
+    #[arg(long, value_parser = parse_size)]
+    size: Option<(usize, usize)>,
+    /// File holding a custom iteration function `f`, see the GUI function editor
+    #[arg(long)]
+    function: Option<PathBuf>,
+    /// Render in tiles of at most this size square, streaming the png to disk (for exports
+    /// too large for a single render)
+    #[arg(long)]
+    tile_size: Option<usize>,
+    /// Use the CPU backend even if OpenCL is available
+    #[arg(long)]
+    cpu: bool,
+}
+
+fn parse_floats<const N: usize>(s: &str) -> Result<[f64; N], String> {
+    let values = s
+        .split(',')
+        .map(|v| {
+            v.trim()
+                .parse::<f64>()
+                .map_err(|err| format!("\"{v}\": {err}"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    values
+        .try_into()
+        .map_err(|_| format!("expected {N} comma separated numbers, got \"{s}\""))
+}
+
+fn parse_complex(s: &str) -> Result<Complex, String> {
+    let [re, im] = parse_floats(s)?;
+    Ok(Complex { re, im })
+}
+
+fn parse_freqs(s: &str) -> Result<Freqs, String> {
+    let [r, g, b] = parse_floats(s)?;
+    Ok(Freqs { r, g, b })
+}
+
+fn parse_size(s: &str) -> Result<(usize, usize), String> {
+    let (w, h) = s
+        .split_once('x')
+        .ok_or_else(|| format!("expected WIDTHxHEIGHT, got \"{s}\""))?;
+    let w = w.trim().parse::<usize>().map_err(|err| err.to_string())?;
+    let h = h.trim().parse::<usize>().map_err(|err| err.to_string())?;
+    Ok((h, w))
+}
+
+impl RenderArgs {
+    fn load_preset(&self) -> Result<Preset, String> {
+        match &self.preset {
+            Some(fpath) if Preset::is_png(fpath) => Preset::load_png(fpath),
+            Some(fpath) => Preset::load(fpath),
+            None => Ok(Preset {
+                params: Default::default(),
+                custom_code: FunctionEditor::default().code,
+                size: INITIAL_IM_MAT_DIMS,
+            }),
+        }
+    }
+
+    /// Preset with all given flags applied on top
+    fn build_preset(&self) -> Result<Preset, String> {
+        let mut preset = self.load_preset()?;
+        let FractalParams {
+            sfparam,
+            vis_type,
+            supersampling,
+        } = &mut preset.params;
+
+        match (self.mode, self.julia_c) {
+            (Some(ModeKind::Mandel), _) => sfparam.mode = FractalMode::Mandel,
+            (Some(ModeKind::Julia), c) | (None, c @ Some(_)) => {
+                let c = c.unwrap_or(sfparam.mode.get_c());
+                sfparam.mode = FractalMode::Julia { c };
+            }
+            (None, None) => {}
+        }
+        if let Some(kind) = self.formula {
+            sfparam.formula = kind.into();
+        }
+        match &mut sfparam.formula {
+            Formula::Multibrot { multibrot } => {
+                if let Some(power) = self.power {
+                    multibrot.power = power;
+                }
+            }
+            Formula::BurningShip { flip } => *flip |= self.flip,
+            _ => {}
+        }
+        if self.deep_zoom {
+            sfparam.deep_zoom.enabled = true;
+        }
+        if let Some(center) = &self.center {
+            let (re, im) = center
+                .split_once(',')
+                .ok_or_else(|| format!("expected RE,IM for the center, got \"{center}\""))?;
+            if sfparam.deep_zoom.enabled {
+                sfparam.deep_zoom.center_re = re.trim().to_string();
+                sfparam.deep_zoom.center_im = im.trim().to_string();
+            } else {
+                sfparam.view_center = parse_complex(center)?;
+            }
+        }
+        if let Some(zoom) = self.zoom {
+            sfparam.zoom = zoom;
+        }
+        if let Some(aspect) = self.aspect {
+            sfparam.auto_aspect = false;
+            sfparam.aspect = aspect;
+        }
+        if let Some(radius) = self.bailout {
+            sfparam.bailout.radius = radius;
+        }
+        if let Some(norm) = self.norm {
+            sfparam.bailout.norm = norm.into();
+        }
+        if let Some(max_iter) = self.max_iter {
+            sfparam.max_iter = max_iter;
+        }
+        sfparam.sync_deep_zoom();
+        if let Some(factor) = self.supersampling {
+            *supersampling = factor;
+        }
+
+        let mut fields: Vec<FractalFieldType> = self.fields.iter().map(|&k| k.into()).collect();
+        if let Some(sequence) = &self.sequence {
+            for field_type in fields.iter_mut() {
+                if let FractalFieldType::Lyapunov { lyapunov } = field_type {
+                    lyapunov.sequence = sequence.clone();
+                    lyapunov.get_c_struct()?;
+                }
+            }
+        }
+        match fields.as_slice() {
+            [] => {}
+            [new_field_type] => match vis_type {
+                // keep a preset's palette, only the field changes
+                FractalVisualisationType::SingleFieldPalette { field_type, .. } => {
+                    *field_type = new_field_type.clone();
+                }
+                _ => {
+                    *vis_type = FractalVisualisationType::SingleFieldCmaped {
+                        field_type: new_field_type.clone(),
+                        cmap_freqs: Default::default(),
+                    }
+                }
+            },
+            [u_field_type, v_field_type] => {
+                *vis_type = FractalVisualisationType::DualFieldImageMap {
+                    u_field_type: u_field_type.clone(),
+                    v_field_type: v_field_type.clone(),
+                    selected_image: Default::default(),
+                    bilinear_interp: true,
+                }
+            }
+            [r_field_type, g_field_type, b_field_type] => {
+                *vis_type = FractalVisualisationType::TriFieldRGB {
+                    r_field_type: r_field_type.clone(),
+                    g_field_type: g_field_type.clone(),
+                    b_field_type: b_field_type.clone(),
+                    normalise_colors: true,
+                }
+            }
+            _ => return Err("at most three fields can be given".to_string()),
+        }
+        if self.basins {
+            if !matches!(vis_type, FractalVisualisationType::NewtonBasins { .. }) {
+                *vis_type = FractalVisualisationType::NewtonBasins {
+                    newton: Default::default(),
+                    coloring: Default::default(),
+                };
+            }
+            if let FractalVisualisationType::NewtonBasins { newton, .. } = vis_type {
+                if !self.poly.is_empty() {
+                    newton.coeffs = self
+                        .poly
+                        .iter()
+                        .rev()
+                        .map(|&re| Complex { re, im: 0.0 })
+                        .collect();
+                }
+                newton.nova |= self.nova;
+                newton.get_c_struct()?;
+            }
+        }
+        if self.buddhabrot {
+            if !matches!(vis_type, FractalVisualisationType::Buddhabrot { .. }) {
+                *vis_type = FractalVisualisationType::Buddhabrot {
+                    buddha: Default::default(),
+                };
+            }
+            if let FractalVisualisationType::Buddhabrot { buddha } = vis_type {
+                buddha.anti |= self.anti;
+                if let Some(passes) = self.passes {
+                    buddha.passes = passes;
+                }
+            }
+        }
+        if let Some(shape) = self.ray_march {
+            if !matches!(vis_type, FractalVisualisationType::RayMarched3D { .. }) {
+                *vis_type = FractalVisualisationType::RayMarched3D {
+                    scene: Default::default(),
+                };
+            }
+            if let FractalVisualisationType::RayMarched3D { scene } = vis_type {
+                scene.shape = shape.into();
+            }
+        }
+        if let Some([yaw, pitch, distance]) = self.camera {
+            match vis_type {
+                FractalVisualisationType::RayMarched3D { scene } => {
+                    scene.camera.yaw = yaw;
+                    scene.camera.pitch = pitch;
+                    scene.camera.distance = distance;
+                }
+                _ => return Err("--camera only applies to ray marched renders".to_string()),
+            }
+        }
+        if let Some(fpath) = &self.gradient {
+            let imported = load_gradient(fpath)?;
+            match vis_type {
+                FractalVisualisationType::SingleFieldPalette { palette, .. } => {
+                    palette.stops = imported.stops;
+                    palette.interpolation = imported.interpolation;
+                }
+                FractalVisualisationType::SingleFieldCmaped { field_type, .. } => {
+                    let field_type = field_type.clone();
+                    *vis_type = FractalVisualisationType::SingleFieldPalette {
+                        field_type,
+                        palette: imported,
+                    };
+                }
+                _ => return Err("--gradient only applies to single field renders".to_string()),
+            }
+        }
+        match vis_type {
+            FractalVisualisationType::SingleFieldCmaped { cmap_freqs, .. } => {
+                if let Some(freqs) = self.freqs {
+                    *cmap_freqs = freqs;
+                }
+            }
+            FractalVisualisationType::DualFieldImageMap { selected_image, .. } => {
+                if let Some(fpath) = &self.sampled_image {
+                    *selected_image = SelectedImage {
+                        path: Some(fpath.clone()),
+                        texture: None,
+                    };
+                }
+                if selected_image.path.is_none() {
+                    return Err("two field renders need a --sampled-image".to_string());
+                }
+            }
+            FractalVisualisationType::RayMarched3D { scene } => {
+                if let Some(freqs) = self.freqs {
+                    scene.trap_freqs = freqs;
+                }
+            }
+            FractalVisualisationType::SingleFieldPalette { .. }
+            | FractalVisualisationType::TriFieldRGB { .. }
+            | FractalVisualisationType::NewtonBasins { .. }
+            | FractalVisualisationType::Buddhabrot { .. } => {}
+        }
+
+        if let Some(size) = self.size {
+            preset.size = size;
+        }
+        preset.params.sfparam.fit_aspect(preset.size);
+        if let Some(fpath) = &self.function {
+            preset.custom_code = std::fs::read_to_string(fpath)
+                .map_err(|err| format!("could not read {}: {err}", fpath.display()))?;
+        }
+        preset
+            .params
+            .sfparam
+            .sync_custom_function(&preset.custom_code);
+        Ok(preset)
+    }
+}
+
+pub fn run(args: RenderArgs) -> Result<(), String> {
+    let preset = args.build_preset()?;
+
+    let opencl_available = ocl::core::get_platform_ids().is_ok_and(|ids| !ids.is_empty());
+    let backend_kind = if opencl_available && !args.cpu {
+        BackendKind::OpenCL
+    } else {
+        if preset.custom_code != FunctionEditor::default().code {
+            eprintln!("warning: custom iteration function is not used by the CPU backend");
+        }
+        BackendKind::Cpu
+    };
+
+    let output = Preset::image_path(&args.output);
+    match args.tile_size {
+        Some(tile_size) => {
+            if !Preset::is_png(&output) {
+                return Err(format!(
+                    "tiled renders are streamed as png, got {}",
+                    output.display()
+                ));
+            }
+            render_tiled(backend_kind, &preset, tile_size, &output, |done, total| {
+                print!("\rtile {done}/{total}");
+                let _ = std::io::stdout().flush();
+            })?;
+            println!();
+        }
+        None => {
+            let mut backend =
+                create_backend(backend_kind, preset.size, Some(preset.custom_code.clone()))?;
+            render_fractal(backend.as_mut(), preset.params.clone())?;
+            preset.save_image(&output, backend.rgb())?;
+        }
+    }
+
+    let (h, w) = preset.size;
+    println!(
+        "rendered {w}x{h} with the {backend_kind:?} backend to {}",
+        output.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use image::{EncodableLayout, ImageReader, ImageResult};
+use ndarray::Array3;
+use std::{fs::File, io::BufWriter, path::Path};
+
+pub fn load_decoded(fpath: impl AsRef<Path>) -> ImageResult<Array3<u8>> {
+    let img = ImageReader::open(fpath)?
+        .with_guessed_format()?
+        .decode()?
+        .into_rgb8();
+    let (w, h) = img.dimensions();
+    let h = h as usize;
+    let w = w as usize;
+    let mut sampled = Array3::<u8>::zeros((h, w, 3));
+    sampled
+        .as_slice_mut()
+        .unwrap()
+        .copy_from_slice(img.as_bytes());
+    Ok(sampled)
+}
+
+pub type PngWriter = png::Writer<BufWriter<File>>;
+
+/// Starts an rgb png of the given (height, width) with (keyword, text) pairs as iTXt chunks,
+/// image data is then written in one go or streamed through `stream_writer`
+pub fn create_png_writer(
+    fpath: impl AsRef<Path>,
+    dims: (usize, usize),
+    text_chunks: &[(&str, String)],
+) -> Result<PngWriter, String> {
+    let file = File::create(fpath).map_err(|err| err.to_string())?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), dims.1 as u32, dims.0 as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    for (keyword, text) in text_chunks {
+        encoder
+            .add_itxt_chunk(keyword.to_string(), text.clone())
+            .map_err(|err| err.to_string())?;
+    }
+    encoder.write_header().map_err(|err| err.to_string())
+}
+
+/// Writes an rgb png with the given (keyword, text) pairs as iTXt chunks
+pub fn save_png_with_text(
+    fpath: impl AsRef<Path>,
+    rgb: &Array3<u8>,
+    text_chunks: &[(&str, String)],
+) -> Result<(), String> {
+    let (h, w, _) = rgb.dim();
+    let mut writer = create_png_writer(fpath, (h, w), text_chunks)?;
+    writer
+        .write_image_data(rgb.as_slice().unwrap())
+        .map_err(|err| err.to_string())
+}
+
+/// Text of the first tEXt/zTXt/iTXt chunk with the given keyword, if any
+pub fn read_png_text(fpath: impl AsRef<Path>, keyword: &str) -> Result<Option<String>, String> {
+    let file = File::open(fpath).map_err(|err| err.to_string())?;
+    let reader = png::Decoder::new(file)
+        .read_info()
+        .map_err(|err| err.to_string())?;
+    let info = reader.info();
+
+    if let Some(chunk) = info.utf8_text.iter().find(|c| c.keyword == keyword) {
+        return chunk.get_text().map(Some).map_err(|err| err.to_string());
+    }
+    if let Some(chunk) = info
+        .compressed_latin1_text
+        .iter()
+        .find(|c| c.keyword == keyword)
+    {
+        return chunk.get_text().map(Some).map_err(|err| err.to_string());
+    }
+    Ok(info
+        .uncompressed_latin1_text
+        .iter()
+        .find(|c| c.keyword == keyword)
+        .map(|c| c.text.clone()))
+}
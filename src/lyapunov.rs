@@ -0,0 +1,89 @@
+//! Lyapunov fractals, the stability of the logistic map x -> r x (1 - x) with the rate r
+//! alternating between a and b following an "AB" sequence. The view's [-2, 2] square covers
+//! the rates [0, 4], a along the real axis and b along the imaginary one.
+
+use egui_inspect::egui;
+use egui_inspect::EguiInspect;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::BackendResult;
+use crate::wrapper_types::LyapunovParam;
+
+/// Longest sequence that fits the kernel's bitmask
+pub static MAX_SEQUENCE_LEN: usize = 32;
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LyapunovParamUI {
+    /// rates in order, e.g. "AABAB"
+    pub sequence: String,
+    /// iterations left out of the exponent, while the orbit settles
+    pub warmup: i32,
+    /// steepness of the squashing of exponents into [0, 1]
+    pub contrast: f64,
+}
+
+impl Default for LyapunovParamUI {
+    fn default() -> Self {
+        Self {
+            sequence: "AB".to_string(),
+            warmup: 100,
+            contrast: 2.0,
+        }
+    }
+}
+
+impl LyapunovParamUI {
+    pub fn get_c_struct(&self) -> BackendResult<LyapunovParam> {
+        let mut sequence = 0;
+        let mut length = 0;
+        for c in self.sequence.chars().filter(|c| !c.is_whitespace()) {
+            if length == MAX_SEQUENCE_LEN {
+                return Err(format!(
+                    "lyapunov sequence longer than {MAX_SEQUENCE_LEN} rates"
+                ));
+            }
+            match c.to_ascii_uppercase() {
+                'A' => {}
+                'B' => sequence |= 1 << length,
+                _ => {
+                    return Err(format!(
+                        "lyapunov sequence can only hold A and B, got '{c}'"
+                    ))
+                }
+            }
+            length += 1;
+        }
+        if length == 0 {
+            return Err("lyapunov sequence is empty".to_string());
+        }
+        Ok(LyapunovParam {
+            sequence,
+            length: length as i32,
+            warmup: self.warmup,
+            contrast: self.contrast,
+        })
+    }
+}
+
+impl EguiInspect for LyapunovParamUI {
+    fn inspect(&self, _label: &str, _ui: &mut egui::Ui) {
+        todo!()
+    }
+
+    fn inspect_mut(&mut self, label: &str, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(label);
+            ui.add(egui::TextEdit::singleline(&mut self.sequence).desired_width(150.0));
+        });
+        if let Err(err) = self.get_c_struct() {
+            ui.colored_label(ui.visuals().error_fg_color, err);
+        }
+        ui.add(egui::Slider::new(&mut self.warmup, 0..=10000).text("warmup"));
+        ui.add(
+            egui::Slider::new(&mut self.contrast, 0.1..=100.0)
+                .logarithmic(true)
+                .text("contrast"),
+        );
+    }
+}
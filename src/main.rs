@@ -1,29 +1,61 @@
 extern crate ocl;
+use backend::{create_backend_or_cpu, BackendKind, BackendResult, RenderBackend};
+use buddhabrot::BuddhabrotParamUI;
+use clap::Parser;
+use color::{linear_to_srgb, srgb_to_linear};
+use deep_zoom::DeepZoom;
 use egui_extras::syntax_highlighting::{highlight, CodeTheme};
 use egui_inspect::egui::{
     self, Color32, ColorImage, DragValue, Image, RichText, TextureHandle, Vec2,
 };
 use egui_inspect::{
     eframe,
-    logging::{log::error, setup_mixed_logger, FileLogOption},
+    logging::{
+        log::{error, warn},
+        setup_mixed_logger, FileLogOption,
+    },
     EguiInspect, InspectNumber,
 };
-use frame_view::FrameView;
-use image::{ColorType, EncodableLayout, ImageReader, ImageResult};
-use ndarray::{Array2, Array3};
-use ocl::{Platform, ProQue};
-use simple_ocl::{try_prog_que_from_source, PairedBuffers2, PairedBuffers3};
-use std::{
-    path::{Path, PathBuf},
-    sync::{Arc, Mutex},
-    thread::JoinHandle,
-};
-
+use frame_view::{FrameView, Navigation};
+use image_io::load_decoded;
+use lyapunov::LyapunovParamUI;
+use ndarray::{Array3, Zip};
+use newton::{BasinColoring, NewtonParamUI};
+use ocl::Platform;
+use palette::Palette;
+use preset::Preset;
+use render_worker::{RenderWorker, Reply};
+use scene::SceneParamUI;
+use serde::{Deserialize, Serialize};
+use shape_trap::{ShapeTrapUI, TrapOutput};
+use std::path::PathBuf;
+use tiled_export::TiledExport;
+
+mod backend;
+mod buddhabrot;
+mod color;
+mod cpu_compute;
+mod deep_zoom;
+mod fractal_compute;
 mod frame_view;
+mod gradient_import;
+mod headless;
+mod image_io;
+mod lyapunov;
+mod newton;
+mod palette;
+mod preset;
+mod render_worker;
+mod scene;
+mod shape_trap;
+mod tiled_export;
 mod wrapper_types;
-use wrapper_types::{BBox, Complex, Freqs, ImDims, ProxType, SFParam};
+use wrapper_types::{
+    BBox, Bailout, Complex, DistanceParams, FormulaParams, Freqs, InteriorChecks, ProxType,
+    SFParam, SmoothParams,
+};
 
-#[derive(Default, EguiInspect, PartialEq, Clone)]
+#[derive(Default, EguiInspect, PartialEq, Clone, Serialize, Deserialize)]
 enum FractalMode {
     #[default]
     Mandel,
@@ -41,17 +73,202 @@ impl FractalMode {
     }
 }
 
+#[derive(EguiInspect, PartialEq, Clone, Copy, Serialize, Deserialize)]
+struct MultibrotParams {
+    #[inspect(min = 1.1, max = 10.0)]
+    power: f64,
+}
+
+impl Default for MultibrotParams {
+    fn default() -> Self {
+        Self { power: 3.0 }
+    }
+}
+
+/// Iteration formula, the built in ones are switched at runtime without recompiling the kernels
+#[derive(Default, EguiInspect, PartialEq, Clone, Copy, Serialize, Deserialize)]
+enum Formula {
+    /// `f` from the function editor
+    #[default]
+    CustomFunction,
+    Mandelbrot,
+    BurningShip {
+        /// mirror vertically, the ship is upright by default
+        flip: bool,
+    },
+    Tricorn,
+    Multibrot {
+        multibrot: MultibrotParams,
+    },
+    Celtic,
+    Buffalo,
+}
+
+impl Formula {
+    fn get_c_struct(&self) -> FormulaParams {
+        let (id, power, flip) = match self {
+            Formula::CustomFunction => (0, 2.0, false),
+            Formula::Mandelbrot => (1, 2.0, false),
+            Formula::BurningShip { flip } => (2, 2.0, *flip),
+            Formula::Tricorn => (3, 2.0, false),
+            Formula::Multibrot { multibrot } => (4, multibrot.power, false),
+            Formula::Celtic => (5, 2.0, false),
+            Formula::Buffalo => (6, 2.0, false),
+        };
+        FormulaParams {
+            id,
+            power,
+            flip: flip as i32,
+        }
+    }
+
+    /// Whether this iterates z^2+c, `default_function` telling whether the custom function is
+    /// the editor's default
+    fn is_quadratic(&self, default_function: bool) -> bool {
+        match self {
+            Formula::Mandelbrot => true,
+            Formula::CustomFunction => default_function,
+            _ => false,
+        }
+    }
+
+    /// Perturbation only follows the z^2+c orbit, a custom function other than the default
+    /// would be silently replaced
+    fn supports_deep_zoom(&self, default_function: bool) -> bool {
+        self.is_quadratic(default_function)
+    }
+}
+
+/// Early exits for interior points, which otherwise iterate all the way to max_iter
+#[derive(PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+struct InteriorChecksUI {
+    /// main cardioid and period 2 bulb, for z^2+c in mandel mode
+    bulb_check: bool,
+    /// Brent cycle detection of the orbit
+    periodicity: bool,
+    /// distance in pixels at which an orbit point counts as revisited, so it holds when zooming
+    tolerance: f64,
+    /// whether the bulb check applies to the current mode and formula, see `sync_interior`
+    #[serde(skip)]
+    bulbs_apply: bool,
+}
+
+impl Default for InteriorChecksUI {
+    fn default() -> Self {
+        Self {
+            bulb_check: true,
+            periodicity: true,
+            tolerance: 1e-6,
+            bulbs_apply: true,
+        }
+    }
+}
+
+impl InteriorChecksUI {
+    /// The bulbs are those of the z^2+c mandel set only, see `Formula::is_quadratic`
+    fn bulbs_apply(mode: &FractalMode, formula: &Formula, default_function: bool) -> bool {
+        matches!(mode, FractalMode::Mandel) && formula.is_quadratic(default_function)
+    }
+
+    fn get_c_struct(
+        &self,
+        mode: &FractalMode,
+        formula: &Formula,
+        default_function: bool,
+    ) -> InteriorChecks {
+        let bulbs = self.bulb_check && Self::bulbs_apply(mode, formula, default_function);
+        InteriorChecks {
+            bulbs: bulbs as i32,
+            periodicity: self.periodicity as i32,
+            tolerance: self.tolerance,
+        }
+    }
+}
+
+impl EguiInspect for InteriorChecksUI {
+    fn inspect(&self, _label: &str, _ui: &mut egui::Ui) {
+        todo!()
+    }
+
+    fn inspect_mut(&mut self, label: &str, ui: &mut egui::Ui) {
+        ui.label(label);
+        ui.add_enabled(
+            self.bulbs_apply,
+            egui::Checkbox::new(&mut self.bulb_check, "bulb_check"),
+        )
+        .on_disabled_hover_text("Only for z^2+c in mandel mode");
+        ui.checkbox(&mut self.periodicity, "periodicity");
+        ui.add(
+            egui::Slider::new(&mut self.tolerance, 1e-12..=1e-1)
+                .logarithmic(true)
+                .text("tolerance"),
+        );
+    }
+}
+
+/// Norm compared against the bailout radius
+#[derive(Default, EguiInspect, PartialEq, Clone, Copy, Serialize, Deserialize)]
+enum BailoutNorm {
+    #[default]
+    Euclidean,
+    /// max of |re| and |im|
+    Square,
+    /// |re| only
+    Real,
+    /// |re| + |im|
+    Manhattan,
+    /// `bailout` from the function editor, the radius is unused
+    Custom,
+}
+
+/// Escape test of the escape time, min prox and buddhabrot orbits, the smooth and distance
+/// fields keep their own (euclidean) radii
+#[derive(EguiInspect, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+struct BailoutUI {
+    #[inspect(log_slider, min = 1.0, max = 1e6)]
+    radius: f64,
+    norm: BailoutNorm,
+}
+
+impl Default for BailoutUI {
+    fn default() -> Self {
+        Self {
+            radius: 2.0,
+            norm: Default::default(),
+        }
+    }
+}
+
+impl BailoutUI {
+    fn get_c_struct(&self) -> Bailout {
+        Bailout {
+            radius: self.radius,
+            norm: self.norm as i32,
+        }
+    }
+}
+
 /// UI for Shared fractal params
-#[derive(EguiInspect, Clone, PartialEq)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 struct SFParamUI {
     mode: FractalMode,
+    formula: Formula,
     view_center: Complex,
-    #[inspect(log_slider, min = 1e-19, max = 2.0)]
     zoom: f64,
-    #[inspect(log_slider, min = 0.1, max = 10.0)]
+    /// aspect follows the image's height over width, for square pixels
+    auto_aspect: bool,
+    /// height over width of the view, a stretch when not automatic
     aspect: f64,
-    #[inspect(log_slider, min = 1.0, max = 10000.0)]
     max_iter: i32,
+    bailout: BailoutUI,
+    interior: InteriorChecksUI,
+    deep_zoom: DeepZoom,
+    /// the function editor holds its default z^2+c, see `sync_custom_function`
+    #[serde(skip)]
+    default_function: bool,
 }
 
 impl Default for SFParamUI {
@@ -60,21 +277,66 @@ impl Default for SFParamUI {
             max_iter: 100,
             view_center: Complex { re: -0.4, im: 0.0 },
             mode: Default::default(),
+            formula: Default::default(),
             zoom: 1.0,
+            auto_aspect: true,
             aspect: 1.0,
+            bailout: Default::default(),
+            interior: Default::default(),
+            deep_zoom: Default::default(),
+            default_function: true,
         }
     }
 }
 
+impl EguiInspect for SFParamUI {
+    fn inspect(&self, _label: &str, _ui: &mut egui::Ui) {
+        todo!()
+    }
+
+    fn inspect_mut(&mut self, label: &str, ui: &mut egui::Ui) {
+        ui.label(label);
+        self.mode.inspect_mut("mode", ui);
+        self.formula.inspect_mut("formula", ui);
+        self.view_center.inspect_mut("view_center", ui);
+        ui.add(
+            egui::Slider::new(&mut self.zoom, 1e-280..=2.0)
+                .logarithmic(true)
+                .text("zoom"),
+        );
+        ui.checkbox(&mut self.auto_aspect, "auto_aspect");
+        // `fit_aspect` overwrites it every frame while automatic
+        ui.add_enabled(
+            !self.auto_aspect,
+            egui::Slider::new(&mut self.aspect, 0.1..=10.0)
+                .logarithmic(true)
+                .text("aspect"),
+        )
+        .on_disabled_hover_text("Follows the image, untick auto_aspect to stretch");
+        ui.add(
+            egui::Slider::new(&mut self.max_iter, 1..=10000)
+                .logarithmic(true)
+                .text("max_iter"),
+        );
+        self.bailout.inspect_mut("bailout", ui);
+        self.interior.inspect_mut("interior", ui);
+        self.deep_zoom.inspect_mut("deep_zoom", ui);
+    }
+}
+
 impl SFParamUI {
     fn get_view_bbox(&self) -> BBox {
+        self.get_view_bbox_about(self.view_center)
+    }
+
+    fn get_view_bbox_about(&self, center: Complex) -> BBox {
         let delta_re = self.zoom;
         let delta_im = self.zoom * self.aspect;
         BBox {
-            left: self.view_center.re - delta_re,
-            right: self.view_center.re + delta_re,
-            bot: self.view_center.im - delta_im,
-            top: self.view_center.im + delta_im,
+            left: center.re - delta_re,
+            right: center.re + delta_re,
+            bot: center.im - delta_im,
+            top: center.im + delta_im,
         }
     }
 
@@ -87,208 +349,175 @@ impl SFParamUI {
             c: self.mode.get_c(),
             view: self.get_view_bbox(),
             max_iter: self.max_iter,
+            formula: self.formula.get_c_struct(),
+            interior: self
+                .interior
+                .get_c_struct(&self.mode, &self.formula, self.default_function),
+            bailout: self.bailout.get_c_struct(),
         }
     }
-}
-
-// ocl source baked into binary at build time
-static OCL_STRUCTS: &str = include_str!("./ocl/mandelstructs.h");
-static OCL_FUNCS: &str = include_str!("./ocl/mandelutils.c");
-static OCL_KERNELS: &str = include_str!("./ocl/mandel.cl");
 
-fn insert_custom_func(custom_func: String) -> String {
-    let funcs = OCL_FUNCS.to_string();
-    let mut sp = funcs.split("//>>");
-    let [before_func, remainder] = [sp.next().unwrap(), sp.next().unwrap()];
-    let rs = remainder.to_string();
-    let mut sp = rs.split("//<<");
-    let [_, after_func] = [sp.next().unwrap(), sp.next().unwrap()];
-    format!("{before_func}{custom_func}{after_func}")
-}
-
-struct FractalCompute {
-    pro_que: ProQue,
-    field_1: PairedBuffers2<f64>,
-    field_2: PairedBuffers2<f64>,
-    field_3: PairedBuffers2<f64>,
-    sampled_path: Option<PathBuf>,
-    sampled_rgb: Option<PairedBuffers3<u8>>,
-    rgb: PairedBuffers3<u8>,
-}
-
-impl FractalCompute {
-    fn new(im_dims: (usize, usize), custom_iter_func: Option<String>) -> ocl::Result<Self> {
-        let ocl_funcs_custom = match custom_iter_func {
-            Some(cf) => insert_custom_func(cf),
-            None => OCL_FUNCS.to_string(),
-        };
-        let full_source = format!("{OCL_STRUCTS}{ocl_funcs_custom}{OCL_KERNELS}");
-        let mut pro_que =
-            try_prog_que_from_source(full_source, "mandel", vec!["-DEXTERNAL_CONCAT".to_string()])?;
-        let field_1 = PairedBuffers2::create_from(Array2::<f64>::zeros(im_dims), &mut pro_que);
-        let field_2 = PairedBuffers2::create_from(Array2::<f64>::zeros(im_dims), &mut pro_que);
-        let field_3 = PairedBuffers2::create_from(Array2::<f64>::zeros(im_dims), &mut pro_que);
-        let (n, m) = im_dims;
-        let rgb = PairedBuffers3::create_from(Array3::<u8>::zeros((n, m, 3)), &mut pro_que);
-        Ok(FractalCompute {
-            pro_que,
-            field_1,
-            field_2,
-            field_3,
-            rgb,
-            sampled_path: None,
-            sampled_rgb: None,
-        })
+    /// Offset from the view center of a point given as a fraction of the image size
+    fn offset_of(&self, at: egui::Pos2) -> Complex {
+        Complex {
+            re: (at.x as f64 - 0.5) * 2.0 * self.zoom,
+            im: (at.y as f64 - 0.5) * 2.0 * self.zoom * self.aspect,
+        }
     }
 
-    fn update_sampled(&mut self, ip: PathBuf) {
-        match load_decoded(&ip) {
-            Ok(sampled) => {
-                let pb = PairedBuffers3::create_from(sampled, &mut self.pro_que);
-                pb.to_device().unwrap();
-                self.sampled_rgb = Some(pb);
-                self.sampled_path = Some(ip);
+    fn translate(&mut self, offset: Complex) {
+        if self.deep_zoom.enabled {
+            if let Err(err) = self.deep_zoom.translate(offset, self.zoom) {
+                error!("{err}");
             }
-            Err(err) => error!("{err}"),
+        } else {
+            self.view_center = self.view_center + offset;
         }
     }
 
-    fn field_ref(&self, i: usize) -> &ocl::Buffer<f64> {
-        match i {
-            1 => &self.field_1.device,
-            2 => &self.field_2.device,
-            3 => &self.field_3.device,
-            _ => {
-                panic!("invalid field index");
+    fn navigate(&mut self, nav: Navigation) {
+        match nav {
+            Navigation::Pan(delta) => {
+                self.translate(Complex {
+                    re: -delta.x as f64 * 2.0 * self.zoom,
+                    im: -delta.y as f64 * 2.0 * self.zoom * self.aspect,
+                });
             }
+            Navigation::ZoomAbout { at, factor } => {
+                let offset = self.offset_of(at);
+                let scale = 1.0 - 1.0 / factor;
+                self.translate(Complex {
+                    re: offset.re * scale,
+                    im: offset.im * scale,
+                });
+                self.zoom /= factor;
+            }
+            Navigation::ZoomToRect(rect) => {
+                // ignore clicks and accidental tiny selections
+                if rect.width().max(rect.height()) > 0.01 {
+                    self.translate(self.offset_of(rect.center()));
+                    self.zoom *= rect.width().max(rect.height()) as f64;
+                }
+            }
+            Navigation::Recenter(at) => self.translate(self.offset_of(at)),
         }
+        self.zoom = self.zoom.clamp(1e-280, 2.0);
     }
 
-    fn run_escape_iter(&mut self, fi: usize, fparam: SFParam) -> ocl::Result<()> {
-        let kernel = self
-            .pro_que
-            .kernel_builder("escape_iter_fpn")
-            .arg(self.field_ref(fi))
-            .arg(fparam)
-            .build()?;
-
-        unsafe {
-            kernel.enq()?;
+    /// Sets the aspect to that of an image of `dims` when automatic
+    fn fit_aspect(&mut self, (h, w): (usize, usize)) {
+        if self.auto_aspect {
+            self.aspect = h as f64 / w as f64;
         }
+    }
 
-        Ok(())
+    /// Greys out the bulb check while it cannot apply
+    fn sync_interior(&mut self) {
+        self.interior.bulbs_apply =
+            InteriorChecksUI::bulbs_apply(&self.mode, &self.formula, self.default_function);
     }
 
-    fn run_min_prox(&mut self, fi: usize, fparam: SFParam, prox_type: ProxType) -> ocl::Result<()> {
-        let kernel = self
-            .pro_que
-            .kernel_builder("min_prox")
-            .arg(self.field_ref(fi))
-            .arg(fparam)
-            .arg(prox_type)
-            .build()?;
+    /// Records whether `code` (that of the function editor) is the default z^2+c
+    fn sync_custom_function(&mut self, code: &str) {
+        self.default_function = code == FunctionEditor::default().code;
+    }
 
-        unsafe {
-            kernel.enq()?;
+    /// Keeps `view_center` following the high precision center while deep zoom is enabled
+    fn sync_deep_zoom(&mut self) {
+        if self.deep_zoom.enabled {
+            if self.deep_zoom.center_re.is_empty() && self.deep_zoom.center_im.is_empty() {
+                self.deep_zoom.set_center(self.view_center);
+            }
+            if let Some(center) = self.deep_zoom.center_approx() {
+                self.view_center = center;
+            }
+        } else if !self.deep_zoom.center_re.is_empty() || !self.deep_zoom.center_im.is_empty() {
+            self.deep_zoom.center_re.clear();
+            self.deep_zoom.center_im.clear();
         }
-
-        Ok(())
     }
 
-    fn run_box_trap_partial(
-        &mut self,
-        fi: usize,
-        fparam: SFParam,
-        box_: BBox,
-        real: bool,
-    ) -> ocl::Result<()> {
-        let kernel_name = if real {
-            "orbit_trap_re"
-        } else {
-            "orbit_trap_im"
-        };
-
-        let kernel = self
-            .pro_que
-            .kernel_builder(kernel_name)
-            .arg(self.field_ref(fi))
-            .arg(fparam)
-            .arg(box_)
-            .build()?;
-
-        unsafe {
-            kernel.enq()?;
+    /// Reference orbit and the matching view relative to it, when deep zoom is enabled
+    fn get_perturbation(&self) -> BackendResult<Option<(SFParam, Vec<Complex>)>> {
+        if !self.deep_zoom.enabled {
+            return Ok(None);
         }
-
-        Ok(())
+        if !self.formula.supports_deep_zoom(self.default_function) {
+            warn!("deep zoom is only available for z^2+c, rendering without it");
+            return Ok(None);
+        }
+        let julia_c = match self.mode {
+            FractalMode::Mandel => None,
+            FractalMode::Julia { c } => Some(c),
+        };
+        let reference = self
+            .deep_zoom
+            .reference_orbit(julia_c, self.zoom, self.max_iter)?;
+        let delta_c_struct = SFParam {
+            view: self.get_view_bbox_about(Complex { re: 0.0, im: 0.0 }),
+            ..self.get_c_struct()
+        };
+        Ok(Some((delta_c_struct, reference)))
     }
+}
 
-    fn run_map_sines(&mut self, freqs: Freqs) -> ocl::Result<()> {
-        let kernel = self
-            .pro_que
-            .kernel_builder("map_sines")
-            .arg(&self.field_1.device)
-            .arg(&self.rgb.device)
-            .arg(freqs)
-            .build()?;
-
-        unsafe {
-            kernel.enq()?;
-        }
+/// Kernel inputs shared by all fields of a render
+#[derive(Clone)]
+struct FieldInputs {
+    sfparam_c: SFParam,
+    perturbation: Option<(SFParam, Vec<Complex>)>,
+    /// the full view, left as is by `tile` and `shifted`
+    frame: BBox,
+}
 
-        Ok(())
+impl FieldInputs {
+    fn new(sfparam: &SFParamUI) -> BackendResult<Self> {
+        let sfparam_c = sfparam.get_c_struct();
+        Ok(Self {
+            sfparam_c,
+            frame: sfparam_c.view,
+            perturbation: sfparam.get_perturbation()?,
+        })
     }
 
-    fn run_pack(&mut self, normalise: bool) -> ocl::Result<()> {
-        let kernel_name = if normalise { "pack_norm" } else { "pack" };
-        let kernel = self
-            .pro_que
-            .kernel_builder(kernel_name)
-            .arg(&self.field_1.device)
-            .arg(&self.field_2.device)
-            .arg(&self.field_3.device)
-            .arg(&self.rgb.device)
-            .build()?;
-
-        unsafe {
-            kernel.enq()?;
+    /// Inputs for the block of pixels at `offset` of size `dims` in an image of size `full_dims`
+    fn tile(
+        &self,
+        full_dims: (usize, usize),
+        offset: (usize, usize),
+        dims: (usize, usize),
+    ) -> Self {
+        let mut tile = self.clone();
+        tile.sfparam_c.view = self.sfparam_c.view.sub_rect(full_dims, offset, dims);
+        if let Some((delta_c, _)) = &mut tile.perturbation {
+            delta_c.view = delta_c.view.sub_rect(full_dims, offset, dims);
         }
-
-        Ok(())
+        tile
     }
 
-    fn run_map_img(&mut self, bilinear: bool) -> ocl::Result<()> {
-        if let Some(sampled) = self.sampled_rgb.as_ref() {
-            let s = sampled.host.shape();
-            let imdims = ImDims {
-                height: s[0] as i32,
-                width: s[1] as i32,
-            };
-            let kernel_name = if bilinear { "map_img3" } else { "map_img2" };
-            let kernel = self
-                .pro_que
-                .kernel_builder(kernel_name)
-                .arg(&self.field_1.device)
-                .arg(&self.field_2.device)
-                .arg(&sampled.device)
-                .arg(&self.rgb.device)
-                .arg(imdims)
-                .build()?;
-
-            unsafe {
-                kernel.enq()?;
-            }
+    /// Inputs with the view moved by a fraction of a pixel, for an image of size `dims`
+    fn shifted(&self, dims: (usize, usize), offset: (f64, f64)) -> Self {
+        let mut shifted = self.clone();
+        shifted.sfparam_c.view = self.sfparam_c.view.shifted(dims, offset);
+        if let Some((delta_c, _)) = &mut shifted.perturbation {
+            delta_c.view = delta_c.view.shifted(dims, offset);
         }
-
-        Ok(())
+        shifted
     }
 }
 
 /// Scalar field selection for visualisation channels
-#[derive(Clone, PartialEq, EguiInspect, Default)]
+#[derive(Clone, PartialEq, EguiInspect, Default, Serialize, Deserialize)]
 enum FractalFieldType {
     #[default]
     ItersToEscape,
+    SmoothItersToEscape {
+        smooth: SmoothParams,
+    },
+    /// Exterior distance estimate, in double precision even when deep zooming
+    DistanceEstimate {
+        distance: DistanceParams,
+    },
     ChainMinProximity {
         prox_type: ProxType,
     },
@@ -300,27 +529,39 @@ enum FractalFieldType {
         #[inspect(name = "box")]
         box_: BBox,
     },
+    /// Root reached by newton's method, as (k+1)/degree, 0 when not converged
+    NewtonRoot {
+        newton: NewtonParamUI,
+    },
+    NewtonIters {
+        newton: NewtonParamUI,
+    },
+    /// Stability of the logistic map with rates a (re) and b (im), over [0, 4] for the view's
+    /// [-2, 2] square, stable above 0.5
+    Lyapunov {
+        #[inspect(name = "sequence")]
+        lyapunov: LyapunovParamUI,
+    },
+    /// Distance of the orbit to a union of shapes
+    ShapeTrapDistance {
+        trap: ShapeTrapUI,
+    },
+    /// Where the orbit was trapped, re in the trapping shape's frame
+    ShapeTrapHitRe {
+        trap: ShapeTrapUI,
+    },
+    ShapeTrapHitIm {
+        trap: ShapeTrapUI,
+    },
+    /// Period of the cycle interior orbits are attracted to, 0 for escaping points or when
+    /// none was detected within max_iter
+    InteriorPeriod,
 }
 
-fn load_decoded(fpath: impl AsRef<Path>) -> ImageResult<Array3<u8>> {
-    let img = ImageReader::open(fpath)?
-        .with_guessed_format()?
-        .decode()?
-        .into_rgb8();
-    let (w, h) = img.dimensions();
-    let h = h as usize;
-    let w = w as usize;
-    let mut sampled = Array3::<u8>::zeros((h, w, 3));
-    sampled
-        .as_slice_mut()
-        .unwrap()
-        .copy_from_slice(img.as_bytes());
-    Ok(sampled)
-}
-
-#[derive(Clone, Default)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 struct SelectedImage {
     path: Option<PathBuf>,
+    #[serde(skip)]
     texture: Option<TextureHandle>,
 }
 
@@ -338,7 +579,14 @@ impl EguiInspect for SelectedImage {
     fn inspect_mut(&mut self, _label: &str, ui: &mut egui::Ui) {
         if ui.button("load sampled image").clicked() {
             if let Some(fpath) = rfd::FileDialog::new().set_directory(".").pick_file() {
-                self.path = Some(fpath.clone());
+                self.path = Some(fpath);
+                self.texture = None;
+            }
+        }
+
+        // path may also have been restored from a preset
+        if self.texture.is_none() {
+            if let Some(fpath) = &self.path {
                 match load_decoded(fpath) {
                     Ok(img) => {
                         let s = img.shape();
@@ -346,7 +594,10 @@ impl EguiInspect for SelectedImage {
                         self.texture =
                             Some(ui.ctx().load_texture("sampled", cimage, Default::default()));
                     }
-                    Err(err) => error!("{err}"),
+                    Err(err) => {
+                        error!("{err}");
+                        self.path = None;
+                    }
                 }
             }
         }
@@ -359,12 +610,16 @@ impl EguiInspect for SelectedImage {
     }
 }
 
-#[derive(Clone, PartialEq, EguiInspect)]
+#[derive(Clone, PartialEq, EguiInspect, Serialize, Deserialize)]
 enum FractalVisualisationType {
     SingleFieldCmaped {
         field_type: FractalFieldType,
         cmap_freqs: Freqs,
     },
+    SingleFieldPalette {
+        field_type: FractalFieldType,
+        palette: Palette,
+    },
     DualFieldImageMap {
         u_field_type: FractalFieldType,
         v_field_type: FractalFieldType,
@@ -377,6 +632,17 @@ enum FractalVisualisationType {
         b_field_type: FractalFieldType,
         normalise_colors: bool,
     },
+    /// Newton or nova fractal coloured by root basin and shaded by convergence speed
+    NewtonBasins {
+        newton: NewtonParamUI,
+        coloring: BasinColoring,
+    },
+    /// Density of escaping (or bounded) orbits, one histogram per colour channel, accumulated
+    /// over progressive passes
+    Buddhabrot { buddha: BuddhabrotParamUI },
+    /// Mandelbulb or Mandelbox ray marched from an orbiting camera, the view only frames the
+    /// film
+    RayMarched3D { scene: SceneParamUI },
 }
 
 impl Default for FractalVisualisationType {
@@ -388,12 +654,26 @@ impl Default for FractalVisualisationType {
     }
 }
 
-#[derive(Clone, Default, PartialEq, EguiInspect)]
+#[derive(Clone, PartialEq, EguiInspect, Serialize, Deserialize)]
 #[inspect(collapsible, no_border)]
+#[serde(default)]
 struct FractalParams {
     #[inspect(name = "Shared")]
     sfparam: SFParamUI,
     vis_type: FractalVisualisationType,
+    /// samples per pixel along each axis, see `render_supersampled`
+    #[inspect(min = 1.0, max = 8.0)]
+    supersampling: i32,
+}
+
+impl Default for FractalParams {
+    fn default() -> Self {
+        Self {
+            sfparam: Default::default(),
+            vis_type: Default::default(),
+            supersampling: 1,
+        }
+    }
 }
 
 /// Basic editing with syntax highlighting through egui_extras, better highlighting available
@@ -413,8 +693,28 @@ impl Default for FunctionEditor {
 // `complex_add: (Complex_t, Complex_t) -> Complex_t`
 // `complex_mult: (Complex_t, Complex_t) -> Complex_t`
 // and `complex_pow: (Complex_t, int) -> Complex_t` are in scope.
+// Optionally `#define CUSTOM_TRAP` with `FPN trap_sdf(Complex_t z)` for the
+// Custom shape trap.
 inline Complex_t f(Complex_t z, Complex_t c) {
   return complex_add(complex_pow(z, 2), c);
+}
+
+// Derivatives of f for the distance estimate, drop these (and the define)
+// to fall back on finite differences.
+#define CUSTOM_DF
+inline Complex_t df_dz(Complex_t z, Complex_t c) {
+  return complex_add(z, z);
+}
+
+inline Complex_t df_dc(Complex_t z, Complex_t c) {
+  return (Complex_t){FONE, FZERO};
+}
+
+// Escape test for the Custom bailout norm, nonzero once z has escaped, drop
+// it (and the define) to fall back on |z| > 2.
+#define CUSTOM_BAILOUT
+inline int bailout(Complex_t z) {
+  return z.re * z.re + z.im * z.im > 4;
 }"
             .to_string(),
             theme: Default::default(),
@@ -451,220 +751,550 @@ impl EguiInspect for FunctionEditor {
 
 type ThreadResult = Result<(), String>;
 
+fn handle_field(
+    fi: usize,
+    helper: &mut dyn RenderBackend,
+    field_type: FractalFieldType,
+    inputs: &FieldInputs,
+) -> BackendResult<()> {
+    let sfparam_c = inputs.sfparam_c;
+    match field_type {
+        FractalFieldType::ItersToEscape => match &inputs.perturbation {
+            Some((delta_c, reference)) => {
+                helper.run_perturbed_escape_iter(fi, *delta_c, reference, None)?;
+            }
+            None => helper.run_escape_iter(fi, sfparam_c)?,
+        },
+        FractalFieldType::SmoothItersToEscape { smooth } => match &inputs.perturbation {
+            Some((delta_c, reference)) => {
+                helper.run_perturbed_escape_iter(fi, *delta_c, reference, Some(smooth))?;
+            }
+            None => helper.run_smooth_escape_iter(fi, sfparam_c, smooth)?,
+        },
+        FractalFieldType::DistanceEstimate { distance } => {
+            helper.run_distance_estimate(fi, sfparam_c, distance)?;
+        }
+        FractalFieldType::ChainMinProximity { prox_type } => {
+            helper.run_min_prox(fi, sfparam_c, prox_type)?;
+        }
+        FractalFieldType::BoxTrapRe { box_ } => {
+            helper.run_box_trap_partial(fi, sfparam_c, box_, true)?;
+        }
+        FractalFieldType::BoxTrapIm { box_ } => {
+            helper.run_box_trap_partial(fi, sfparam_c, box_, false)?;
+        }
+        FractalFieldType::ShapeTrapDistance { trap } => {
+            helper.run_shape_trap(fi, sfparam_c, trap.get_c_struct()?, TrapOutput::Distance)?;
+        }
+        FractalFieldType::ShapeTrapHitRe { trap } => {
+            helper.run_shape_trap(fi, sfparam_c, trap.get_c_struct()?, TrapOutput::HitRe)?;
+        }
+        FractalFieldType::ShapeTrapHitIm { trap } => {
+            helper.run_shape_trap(fi, sfparam_c, trap.get_c_struct()?, TrapOutput::HitIm)?;
+        }
+        FractalFieldType::NewtonRoot { newton } => {
+            helper.run_newton(fi, sfparam_c, newton.get_c_struct()?, true)?;
+        }
+        FractalFieldType::NewtonIters { newton } => {
+            helper.run_newton(fi, sfparam_c, newton.get_c_struct()?, false)?;
+        }
+        FractalFieldType::Lyapunov { lyapunov } => {
+            helper.run_lyapunov(fi, sfparam_c, lyapunov.get_c_struct()?)?;
+        }
+        FractalFieldType::InteriorPeriod => helper.run_interior_period(fi, sfparam_c)?,
+    }
+    Ok(())
+}
+
+fn render_fractal(helper: &mut dyn RenderBackend, frac_param: FractalParams) -> ThreadResult {
+    let inputs = FieldInputs::new(&frac_param.sfparam)?;
+    render_supersampled(
+        helper,
+        &frac_param.vis_type,
+        &inputs,
+        frac_param.supersampling,
+        &|| false,
+    )
+}
+
+/// Renders `factor`^2 passes on a grid of sub-pixel offsets and averages them in linear light,
+/// same as rendering at `factor` times the size and downsampling. Stops between passes once
+/// `cancelled`, leaving a partial image
+fn render_supersampled(
+    helper: &mut dyn RenderBackend,
+    vis_type: &FractalVisualisationType,
+    inputs: &FieldInputs,
+    factor: i32,
+    cancelled: &dyn Fn() -> bool,
+) -> ThreadResult {
+    if let FractalVisualisationType::Buddhabrot { buddha } = vis_type {
+        // splatted rather than sampled per pixel, so no sub-pixel passes, the histogram passes
+        // are all accumulated instead
+        render_fields(helper, vis_type.clone(), inputs)?;
+        while helper.histogram_state().passes < buddha.passes as usize && !cancelled() {
+            render_fields(helper, vis_type.clone(), inputs)?;
+        }
+        return Ok(());
+    }
+    if factor <= 1 {
+        return render_fields(helper, vis_type.clone(), inputs);
+    }
+
+    let (h, w, _) = helper.rgb().dim();
+    let mut acc = Array3::<f32>::zeros((h, w, 3));
+    for a in 0..factor {
+        for b in 0..factor {
+            if cancelled() {
+                return Ok(());
+            }
+            let offset = (
+                (a as f64 + 0.5) / factor as f64 - 0.5,
+                (b as f64 + 0.5) / factor as f64 - 0.5,
+            );
+            render_fields(helper, vis_type.clone(), &inputs.shifted((h, w), offset))?;
+            Zip::from(&mut acc)
+                .and(helper.rgb())
+                .for_each(|acc, &v| *acc += srgb_to_linear(v));
+        }
+    }
+    let n = (factor * factor) as f32;
+    Zip::from(helper.rgb_mut())
+        .and(&acc)
+        .for_each(|v, &acc| *v = linear_to_srgb(acc / n));
+    Ok(())
+}
+
+fn render_fields(
+    helper: &mut dyn RenderBackend,
+    vis_type: FractalVisualisationType,
+    inputs: &FieldInputs,
+) -> ThreadResult {
+    match vis_type {
+        FractalVisualisationType::SingleFieldCmaped {
+            field_type,
+            cmap_freqs: freqs,
+        } => {
+            handle_field(1, helper, field_type, inputs)?;
+            helper.run_map_sines(freqs)?;
+        }
+        FractalVisualisationType::SingleFieldPalette {
+            field_type,
+            palette,
+        } => {
+            handle_field(1, helper, field_type, inputs)?;
+            helper.run_map_palette(&palette.lut(), palette.mapping())?;
+        }
+        FractalVisualisationType::DualFieldImageMap {
+            u_field_type,
+            v_field_type,
+            selected_image,
+            bilinear_interp,
+        } => {
+            if helper.sampled_path() != selected_image.path.as_ref() {
+                if let Some(ip) = &selected_image.path {
+                    helper.update_sampled(ip.clone());
+                }
+            };
+            if helper.has_sampled() {
+                handle_field(1, helper, u_field_type, inputs)?;
+                handle_field(2, helper, v_field_type, inputs)?;
+                // TODO: prox field not normalised for UV coords
+                helper.run_map_img(bilinear_interp)?;
+            }
+        }
+        FractalVisualisationType::TriFieldRGB {
+            r_field_type,
+            g_field_type,
+            b_field_type,
+            normalise_colors,
+        } => {
+            handle_field(1, helper, r_field_type, inputs)?;
+            handle_field(2, helper, g_field_type, inputs)?;
+            handle_field(3, helper, b_field_type, inputs)?;
+            helper.run_pack(normalise_colors)?;
+        }
+        FractalVisualisationType::NewtonBasins { newton, coloring } => {
+            let newton_c = newton.get_c_struct()?;
+            helper.run_newton(1, inputs.sfparam_c, newton_c, true)?;
+            helper.run_newton(2, inputs.sfparam_c, newton_c, false)?;
+            let colors = coloring.colors(newton_c.degree as usize);
+            helper.run_map_basins(&colors, coloring.shading)?;
+        }
+        FractalVisualisationType::Buddhabrot { buddha } => {
+            helper.run_buddhabrot_pass(inputs.sfparam_c, buddha.get_c_struct())?;
+            helper.run_map_histograms(buddha.exposure)?;
+            helper.run_pack(false)?;
+        }
+        FractalVisualisationType::RayMarched3D { scene } => {
+            helper.run_ray_march(inputs.sfparam_c, scene.get_c_struct(inputs.frame))?;
+        }
+    };
+    helper.read_rgb()?;
+    Ok(())
+}
+
+/// As `render_fractal`, but a single pass for buddhabrots so the viewer can show the histograms
+/// filling in, and stopping early once `cancelled`
+fn render_progressive(
+    helper: &mut dyn RenderBackend,
+    frac_param: FractalParams,
+    cancelled: &dyn Fn() -> bool,
+) -> ThreadResult {
+    let inputs = FieldInputs::new(&frac_param.sfparam)?;
+    match &frac_param.vis_type {
+        FractalVisualisationType::Buddhabrot { .. } => {
+            render_fields(helper, frac_param.vis_type, &inputs)
+        }
+        _ => render_supersampled(
+            helper,
+            &frac_param.vis_type,
+            &inputs,
+            frac_param.supersampling,
+            cancelled,
+        ),
+    }
+}
+
+static PREVIEW_IM_MAT_DIMS: (usize, usize) = (240, 320);
+
+/// Julia set preview for a constant picked on the mandel view
+struct JuliaPicker {
+    enabled: bool,
+    follow_hover: bool,
+    c: Complex,
+    preview: FrameView,
+    worker: RenderWorker,
+    old_fp: Option<FractalParams>,
+}
+
+impl JuliaPicker {
+    fn new(backend_kind: BackendKind, backend: Box<dyn RenderBackend>) -> Self {
+        Self {
+            enabled: false,
+            follow_hover: true,
+            c: Complex::default(),
+            preview: FrameView::new(PREVIEW_IM_MAT_DIMS),
+            worker: RenderWorker::new(backend_kind, backend),
+            old_fp: None,
+        }
+    }
+
+    /// Same visualisation as the main view, but of the whole julia set for the picked constant
+    fn preview_params(&self, fp: &FractalParams) -> FractalParams {
+        let mut preview_fp = fp.clone();
+        preview_fp.sfparam = SFParamUI {
+            mode: FractalMode::Julia { c: self.c },
+            formula: fp.sfparam.formula,
+            view_center: Complex { re: 0.0, im: 0.0 },
+            zoom: 1.6,
+            auto_aspect: true,
+            aspect: PREVIEW_IM_MAT_DIMS.0 as f64 / PREVIEW_IM_MAT_DIMS.1 as f64,
+            max_iter: fp.sfparam.max_iter,
+            bailout: fp.sfparam.bailout,
+            interior: fp.sfparam.interior,
+            deep_zoom: Default::default(),
+            default_function: fp.sfparam.default_function,
+        };
+        preview_fp
+    }
+
+    fn pick(&mut self, sfparam: &SFParamUI, main_view: &FrameView) {
+        if !self.enabled || sfparam.mode != FractalMode::Mandel {
+            return;
+        }
+        let hovered = main_view.hovered.filter(|_| self.follow_hover);
+        if let Some(at) = main_view.clicked.or(hovered) {
+            self.c = sfparam.view_center + sfparam.offset_of(at);
+        }
+    }
+
+    /// Shows finished previews and requests the next one if the parameters have moved on
+    fn poll(&mut self, fp: &FractalParams) {
+        for reply in self.worker.poll() {
+            match reply {
+                Reply::Rendered { rgb, .. } => self.preview.update(&rgb),
+                Reply::Failed(err) => error!("Julia preview: {err}"),
+                Reply::Recompiled(Err(err)) => error!("Julia preview: {err}"),
+                _ => {}
+            }
+        }
+
+        if self.enabled {
+            let preview_fp = self.preview_params(fp);
+            if self.old_fp.as_ref() != Some(&preview_fp) {
+                self.worker.render(preview_fp.clone(), false);
+                self.old_fp = Some(preview_fp);
+            }
+        }
+    }
+
+    fn recompile(&mut self, backend_kind: BackendKind, code: String) {
+        self.worker.recompile(backend_kind, code);
+        self.old_fp = None;
+    }
+}
+
 struct FractalViewer {
     fp: FractalParams,
     old_fp: FractalParams,
     editor: FunctionEditor,
     size_selection: (usize, usize),
+    /// size the worker was last asked to render at
+    worker_size: (usize, usize),
+    /// size selection follows the central panel
+    fit_to_panel: bool,
+    backend_kind: BackendKind,
+    recompile_pending: bool,
     error: Option<String>,
     iters_image: FrameView,
-    ocl_helper: Arc<Mutex<FractalCompute>>,
-    join_handle: Option<JoinHandle<ThreadResult>>,
+    worker: RenderWorker,
+    /// show a coarse image first while rendering
+    progressive: bool,
+    /// buddhabrot samples shown so far, passes continue while the last one succeeded
+    histogram_samples: usize,
+    histogram_passes: usize,
+    last_render_ok: bool,
+    julia_picker: JuliaPicker,
+    tiled_export: TiledExport,
 }
 
 static INITIAL_IM_MAT_DIMS: (usize, usize) = (768, 1280);
 
 impl FractalViewer {
-    fn new() -> Self {
+    fn new(backend_kind: BackendKind) -> Self {
         let mut old_fp = FractalParams::default();
         old_fp.sfparam.max_iter = 0;
 
+        let (backend_kind, backend) = create_backend_or_cpu(backend_kind, INITIAL_IM_MAT_DIMS);
+        let (preview_kind, preview_backend) =
+            create_backend_or_cpu(backend_kind, PREVIEW_IM_MAT_DIMS);
+
         Self {
             editor: Default::default(),
             iters_image: FrameView::new(INITIAL_IM_MAT_DIMS),
-            ocl_helper: Arc::new(Mutex::new(
-                FractalCompute::new(INITIAL_IM_MAT_DIMS, None).unwrap(),
-            )),
-            join_handle: None,
+            worker: RenderWorker::new(backend_kind, backend),
+            progressive: true,
+            histogram_samples: 0,
+            histogram_passes: 0,
+            last_render_ok: true,
+            julia_picker: JuliaPicker::new(preview_kind, preview_backend),
+            tiled_export: TiledExport::new(INITIAL_IM_MAT_DIMS),
             fp: Default::default(),
             old_fp,
             error: None,
             size_selection: INITIAL_IM_MAT_DIMS,
+            worker_size: INITIAL_IM_MAT_DIMS,
+            fit_to_panel: false,
+            backend_kind,
+            recompile_pending: false,
         }
     }
 
-    fn handle_field(
-        fi: usize,
-        helper: &mut FractalCompute,
-        field_type: FractalFieldType,
-        sfparam_c: SFParam,
-    ) -> ocl::Result<()> {
-        match field_type {
-            FractalFieldType::ItersToEscape => {
-                helper.run_escape_iter(fi, sfparam_c)?;
-            }
-            FractalFieldType::ChainMinProximity { prox_type } => {
-                helper.run_min_prox(fi, sfparam_c, prox_type)?;
-            }
-            FractalFieldType::BoxTrapRe { box_ } => {
-                helper.run_box_trap_partial(fi, sfparam_c, box_, true)?;
-            }
-            FractalFieldType::BoxTrapIm { box_ } => {
-                helper.run_box_trap_partial(fi, sfparam_c, box_, false)?;
+    /// Applies the replies of the render worker received since the last frame
+    fn collect_results(&mut self) {
+        for reply in self.worker.poll() {
+            match reply {
+                Reply::Rendered {
+                    rgb,
+                    histogram_samples,
+                    histogram_passes,
+                    ..
+                } => {
+                    self.iters_image.update(&rgb);
+                    self.histogram_samples = histogram_samples;
+                    self.histogram_passes = histogram_passes;
+                    self.last_render_ok = true;
+                }
+                Reply::Superseded => {}
+                Reply::Failed(err) => {
+                    error!("Error on render thread: {err}");
+                    self.last_render_ok = false;
+                }
+                Reply::Recompiled(result) => match result {
+                    Ok(_) => {
+                        self.old_fp.sfparam.max_iter = 0; // trigger recompute
+                        self.error = None;
+                    }
+                    Err(err) => self.error = Some(err),
+                },
+                Reply::Resized { size } => self.iters_image = FrameView::new(size),
+                Reply::Saved(result) => {
+                    if let Err(err) = result {
+                        error!("{err}");
+                    }
+                }
             }
         }
-        Ok(())
-    }
-
-    fn run_kernel_in_background(&mut self) {
-        let helper_arc = self.ocl_helper.clone();
-        let frac_param = self.fp.clone();
-        let dims = self.iters_image.dims;
-
-        self.join_handle = Some(std::thread::spawn(move || {
-            let FractalParams { sfparam, vis_type } = frac_param;
-            let sfparam_c = sfparam.get_c_struct();
-            match helper_arc.try_lock() {
-                Ok(mut guard) => {
-                    guard.pro_que.set_dims(dims);
-                    match vis_type {
-                        FractalVisualisationType::SingleFieldCmaped {
-                            field_type,
-                            cmap_freqs: freqs,
-                        } => {
-                            Self::handle_field(1, &mut guard, field_type, sfparam_c)?;
-                            guard.run_map_sines(freqs)?;
-                        }
-                        FractalVisualisationType::DualFieldImageMap {
-                            u_field_type,
-                            v_field_type,
-                            selected_image,
-                            bilinear_interp,
-                        } => {
-                            if guard.sampled_path != selected_image.path {
-                                if let Some(ip) = &selected_image.path {
-                                    guard.update_sampled(ip.clone());
-                                    // update_sampled changes que size
-                                    guard.pro_que.set_dims(dims);
-                                }
-                            };
-                            if guard.sampled_rgb.is_some() {
-                                Self::handle_field(1, &mut guard, u_field_type, sfparam_c)?;
-                                Self::handle_field(2, &mut guard, v_field_type, sfparam_c)?;
-                                // TODO: prox field not normalised for UV coords
-                                guard.run_map_img(bilinear_interp)?;
-                            }
-                        }
-                        FractalVisualisationType::TriFieldRGB {
-                            r_field_type,
-                            g_field_type,
-                            b_field_type,
-                            normalise_colors,
-                        } => {
-                            Self::handle_field(1, &mut guard, r_field_type, sfparam_c)?;
-                            Self::handle_field(2, &mut guard, g_field_type, sfparam_c)?;
-                            Self::handle_field(3, &mut guard, b_field_type, sfparam_c)?;
-                            guard.run_pack(normalise_colors)?;
-                        }
-                    };
-                    guard.rgb.from_device()?;
-                    Ok(())
-                }
-                Err(_) => Err("mutex is locked".to_string()),
+    }
+
+    /// Whether the buddhabrot histograms still have passes to go
+    fn passes_pending(&self) -> bool {
+        match &self.fp.vis_type {
+            FractalVisualisationType::Buddhabrot { buddha } => {
+                self.last_render_ok && self.histogram_passes < buddha.passes as usize
             }
-        }));
+            _ => false,
+        }
     }
 
-    fn collect_result(&mut self) {
-        let handle = self.join_handle.take().unwrap();
-        match handle.join().expect("thread join error") {
-            Ok(_) => match self.ocl_helper.try_lock() {
-                Ok(guard) => self.iters_image.update(&guard.rgb.host),
-                Err(err) => error!("could not aquire mutex in update: {err}"),
-            },
-            Err(err) => error!("Error on other thread: {}", err),
+    /// Queued behind any pending renders
+    fn recompile(&mut self) {
+        if self.backend_kind == BackendKind::Cpu
+            && self.editor.code != FunctionEditor::default().code
+        {
+            warn!("Custom iteration function is not used by the CPU backend");
         }
+        self.worker
+            .recompile(self.backend_kind, self.editor.code.clone());
+        self.julia_picker
+            .recompile(self.backend_kind, self.editor.code.clone());
     }
 
-    fn try_recompile(&mut self) {
-        if self.join_handle.is_none() {
-            if let Ok(mut guard) = self.ocl_helper.try_lock() {
-                match FractalCompute::new(self.size_selection, Some(self.editor.code.clone())) {
-                    Ok(new_helper) => {
-                        *guard = new_helper;
-                        self.iters_image = FrameView::new(self.size_selection);
-                        self.old_fp.sfparam.max_iter = 0; // trigger recompute
-                        self.error = None;
-                    }
-                    Err(err) => self.error = Some(format!("{err}")),
-                }
-            }
-        } else {
-            self.error = Some(format!("Could not recompile, GPU was busy."));
+    fn current_preset(&self) -> Preset {
+        Preset {
+            params: self.fp.clone(),
+            custom_code: self.editor.code.clone(),
+            size: self.size_selection,
         }
     }
 
-    fn save_image(&self, fpath: impl AsRef<Path>) -> ImageResult<()> {
-        if let Ok(guard) = self.ocl_helper.try_lock() {
-            image::save_buffer(
-                fpath,
-                guard.rgb.host.as_slice().unwrap(),
-                self.size_selection.1 as u32,
-                self.size_selection.0 as u32,
-                ColorType::Rgb8,
-            )?;
-        };
-        Ok(())
+    fn apply_preset(&mut self, preset: Preset) {
+        if preset.custom_code != self.editor.code {
+            self.editor.code = preset.custom_code;
+            self.recompile_pending = true;
+        }
+        if preset.size != self.size_selection {
+            self.size_selection = preset.size;
+            self.fit_to_panel = false;
+        }
+        self.fp = preset.params;
+    }
+
+    /// Saves once the renders queued so far are done
+    fn save_image(&self, fpath: PathBuf) {
+        self.worker.save(fpath, self.current_preset());
     }
 }
 
 impl eframe::App for FractalViewer {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        let job_still_running = match &mut self.join_handle {
-            Some(handle) => {
-                if handle.is_finished() {
-                    self.collect_result();
-                    false
-                } else {
-                    true
-                }
-            }
-            None => false,
-        };
+        self.collect_results();
 
-        let mut status_text = RichText::new("GPU Busy").color(Color32::RED);
-        let params_updated = self.old_fp != self.fp;
-        if !job_still_running {
-            if params_updated {
-                self.run_kernel_in_background();
-                self.old_fp = self.fp.clone();
-            } else {
-                status_text = RichText::new("GPU Waiting").color(Color32::GREEN);
-            }
+        self.fp.sfparam.sync_deep_zoom();
+        self.fp.sfparam.fit_aspect(self.size_selection);
+        self.fp.sfparam.sync_custom_function(&self.editor.code);
+        self.fp.sfparam.sync_interior();
+
+        if self.recompile_pending {
+            self.recompile_pending = false;
+            self.recompile();
+        }
+        // the view is resized once the worker replies, renders queued before then are shown at
+        // the old size
+        if self.size_selection != self.worker_size {
+            self.worker.resize(self.size_selection);
+            self.worker_size = self.size_selection;
+            self.old_fp.sfparam.max_iter = 0; // trigger recompute
         }
+        // every change is sent, superseding whatever is still queued or running, while further
+        // buddhabrot passes wait for the previous one so they do not supersede each other
+        if self.old_fp != self.fp || (self.passes_pending() && !self.worker.is_busy()) {
+            self.worker.render(self.fp.clone(), self.progressive);
+            self.old_fp = self.fp.clone();
+        }
+        let status_text = if self.worker.is_busy() {
+            RichText::new("GPU Busy").color(Color32::RED)
+        } else {
+            RichText::new("GPU Waiting").color(Color32::GREEN)
+        };
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            self.iters_image.inspect_mut("", ui);
-        });
+        self.julia_picker.pick(&self.fp.sfparam, &self.iters_image);
+        self.julia_picker.poll(&self.fp);
+        if self.worker.is_busy() || self.julia_picker.worker.is_busy() || self.passes_pending() {
+            // renders should land without waiting for further input
+            ctx.request_repaint();
+        }
 
         egui::SidePanel::right("Controls").show(ctx, |ui| {
             ui.label(status_text);
+            if let FractalVisualisationType::Buddhabrot { buddha } = &self.fp.vis_type {
+                ui.label(format!(
+                    "{} samples, pass {} of {}",
+                    self.histogram_samples, self.histogram_passes, buddha.passes
+                ));
+            }
 
-            if ui.button("Save image").clicked() {
-                if let Some(fpath) = rfd::FileDialog::new().set_directory(".").save_file() {
-                    if let Err(err) = self.save_image(fpath) {
-                        error!("{err}");
+            ui.horizontal(|ui| {
+                if ui.button("Save image").clicked() {
+                    if let Some(fpath) = rfd::FileDialog::new().set_directory(".").save_file() {
+                        self.save_image(fpath);
+                    };
+                };
+
+                if ui.button("Save preset").clicked() {
+                    if let Some(fpath) = rfd::FileDialog::new()
+                        .set_directory(".")
+                        .add_filter("RON preset", &["ron"])
+                        .save_file()
+                    {
+                        if let Err(err) = self.current_preset().save(fpath) {
+                            error!("{err}");
+                        };
+                    };
+                };
+
+                if ui.button("Load from image").clicked() {
+                    if let Some(fpath) = rfd::FileDialog::new()
+                        .set_directory(".")
+                        .add_filter("PNG image", &["png"])
+                        .pick_file()
+                    {
+                        match Preset::load_png(fpath) {
+                            Ok(preset) => self.apply_preset(preset),
+                            Err(err) => error!("{err}"),
+                        }
                     };
                 };
-            };
+
+                if ui.button("Load preset").clicked() {
+                    if let Some(fpath) = rfd::FileDialog::new()
+                        .set_directory(".")
+                        .add_filter("RON preset", &["ron"])
+                        .pick_file()
+                    {
+                        match Preset::load(fpath) {
+                            Ok(preset) => self.apply_preset(preset),
+                            Err(err) => error!("{err}"),
+                        }
+                    };
+                };
+            });
 
             egui::ScrollArea::vertical().show(ui, |ui| {
                 ui.collapsing("Kernel settings", |ui| {
-                    // TODO: custom function requires recompilation but not buffer changes and size
-                    // change requires buffer change but not recompilation. Currently just swapping
-                    // helper for simplicity.
-
-                    ui.label("Custom iteration function:");
+                    ui.label("Custom iteration function (for the CustomFunction formula):");
                     self.editor.inspect_mut("Custom function", ui);
 
-                    ui.horizontal(|ui| {
-                        ui.label("Generated image size: ");
-                        ui.add(DragValue::new(&mut self.size_selection.0));
-                        ui.add(DragValue::new(&mut self.size_selection.1));
+                    self.backend_kind.inspect_mut("Backend", ui);
+
+                    ui.checkbox(&mut self.fit_to_panel, "Fit to panel");
+                    ui.add_enabled_ui(!self.fit_to_panel, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Generated image size: ");
+                            ui.add(DragValue::new(&mut self.size_selection.0).range(1..=16384));
+                            ui.add(DragValue::new(&mut self.size_selection.1).range(1..=16384));
+                        });
                     });
 
-                    if ui.button("Recompile").clicked() {
-                        self.try_recompile();
+                    ui.checkbox(&mut self.progressive, "Progressive refinement")
+                        .on_hover_text("Coarse image first, then refined, while navigating");
+
+                    if ui
+                        .button("Recompile")
+                        .on_hover_text("Needed for the custom function and backend only")
+                        .clicked()
+                    {
+                        self.recompile();
                     }
 
                     if let Some(err) = &self.error {
@@ -672,15 +1302,70 @@ impl eframe::App for FractalViewer {
                     }
                 });
 
+                ui.collapsing("Julia picker", |ui| {
+                    let picker = &mut self.julia_picker;
+                    ui.checkbox(&mut picker.enabled, "Pick c on the mandel view");
+                    ui.checkbox(&mut picker.follow_hover, "Follow cursor (click to pick)");
+                    picker.c.inspect_mut("c", ui);
+                    if picker.enabled {
+                        picker.preview.show(ui);
+                    }
+                    if ui.button("Open in main view").clicked() {
+                        let sfparam = &mut self.fp.sfparam;
+                        sfparam.mode = FractalMode::Julia { c: picker.c };
+                        sfparam.view_center = Complex { re: 0.0, im: 0.0 };
+                        sfparam.zoom = 1.6;
+                        sfparam.deep_zoom.enabled = false;
+                        picker.enabled = false;
+                    }
+                });
+
+                ui.collapsing("Tiled export", |ui| {
+                    let preset = self.current_preset();
+                    self.tiled_export.show(ui, self.backend_kind, preset);
+                });
+
                 self.fp.inspect_mut("Fractal parameters", ui);
             });
         });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if self.fit_to_panel {
+                let size = ui.available_size() * ctx.pixels_per_point();
+                let fit = ((size.y as usize).max(1), (size.x as usize).max(1));
+                if fit != self.size_selection {
+                    self.size_selection = fit;
+                    ctx.request_repaint();
+                }
+            }
+            if let Some(nav) = self.iters_image.show(ui) {
+                match &mut self.fp.vis_type {
+                    FractalVisualisationType::RayMarched3D { scene } => scene.navigate(nav),
+                    _ => self.fp.sfparam.navigate(nav),
+                }
+            }
+        });
     }
 }
 
 fn main() -> eframe::Result<()> {
-    // NOTE: Work around to strange segfault issue when building proque in eframe::App
-    dbg!(Platform::default());
+    if std::env::args().nth(1).as_deref() == Some("render") {
+        let args = headless::RenderArgs::parse_from(std::env::args().skip(1));
+        if let Err(err) = headless::run(args) {
+            eprintln!("error: {err}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let opencl_available = ocl::core::get_platform_ids().is_ok_and(|ids| !ids.is_empty());
+    let backend_kind = if opencl_available && !std::env::args().any(|a| a == "--cpu") {
+        // NOTE: Work around to strange segfault issue when building proque in eframe::App
+        dbg!(Platform::default());
+        BackendKind::OpenCL
+    } else {
+        BackendKind::Cpu
+    };
 
     // TODO: Include gui log view?
     setup_mixed_logger(FileLogOption::DefaultTempDir {
@@ -690,6 +1375,6 @@ fn main() -> eframe::Result<()> {
     eframe::run_native(
         "Fractal viewer",
         Default::default(),
-        Box::new(|_cc| Ok(Box::new(FractalViewer::new()))),
+        Box::new(move |_cc| Ok(Box::new(FractalViewer::new(backend_kind)))),
     )
 }
@@ -0,0 +1,266 @@
+//! Newton and Nova fractals of a user polynomial. Roots are found on the host, the kernels
+//! report which one each pixel converges to and how fast.
+
+use egui_inspect::egui::{self, Button, DragValue};
+use egui_inspect::{EguiInspect, InspectNumber};
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::BackendResult;
+use crate::palette::{ColorStop, Interpolation, Palette};
+use crate::wrapper_types::{Complex, NewtonParam, MAX_NEWTON_DEGREE};
+
+static DURAND_KERNER_MAX_ITER: usize = 1000;
+
+const ZERO: Complex = Complex { re: 0.0, im: 0.0 };
+const ONE: Complex = Complex { re: 1.0, im: 0.0 };
+
+/// All roots of the polynomial with `coeffs[k]` the coefficient of z^k, the last one non zero,
+/// by Durand-Kerner (simultaneous Weierstrass) iteration
+pub fn durand_kerner(coeffs: &[Complex]) -> Vec<Complex> {
+    let n = coeffs.len() - 1;
+    let lead = coeffs[n];
+    let monic: Vec<Complex> = coeffs.iter().map(|&a| a / lead).collect();
+    let eval = |z: Complex| monic.iter().rev().fold(ZERO, |acc, &a| acc * z + a);
+
+    // powers of a point that is neither real nor a root of unity
+    let seed = Complex { re: 0.4, im: 0.9 };
+    let mut roots: Vec<Complex> = std::iter::successors(Some(ONE), |&r| Some(r * seed))
+        .take(n)
+        .collect();
+    for _ in 0..DURAND_KERNER_MAX_ITER {
+        let mut change: f64 = 0.0;
+        for k in 0..n {
+            let denom = (0..n)
+                .filter(|&l| l != k)
+                .fold(ONE, |acc, l| acc * (roots[k] - roots[l]));
+            let delta = eval(roots[k]) / denom;
+            roots[k] = roots[k] - delta;
+            change = change.max(delta.norm_sqr());
+        }
+        if change < 1e-28 {
+            break;
+        }
+    }
+    roots
+}
+
+/// UI for the newton fields, z -> z - a p(z)/p'(z) (+ c for nova)
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NewtonParamUI {
+    /// coefficient of z^k at k
+    pub coeffs: Vec<Complex>,
+    pub relaxation: Complex,
+    pub nova: bool,
+    /// starting point of nova in mandel mode, a critical point of the newton map (any simple
+    /// root of p)
+    pub start: Complex,
+    pub tolerance: f64,
+}
+
+impl Default for NewtonParamUI {
+    fn default() -> Self {
+        // z^3 - 1
+        Self {
+            coeffs: vec![Complex { re: -1.0, im: 0.0 }, ZERO, ZERO, ONE],
+            relaxation: ONE,
+            nova: false,
+            start: ONE,
+            tolerance: 1e-6,
+        }
+    }
+}
+
+impl NewtonParamUI {
+    /// Polynomial without its zero leading coefficients
+    fn trimmed_coeffs(&self) -> &[Complex] {
+        let len = self
+            .coeffs
+            .iter()
+            .rposition(|&a| a != ZERO)
+            .map_or(0, |k| k + 1);
+        &self.coeffs[..len]
+    }
+
+    pub fn get_c_struct(&self) -> BackendResult<NewtonParam> {
+        let coeffs = self.trimmed_coeffs();
+        let degree = coeffs.len().saturating_sub(1);
+        if degree < 1 {
+            return Err("newton polynomial needs degree at least 1".to_string());
+        }
+        if degree > MAX_NEWTON_DEGREE {
+            return Err(format!(
+                "newton polynomial of degree {degree}, at most {MAX_NEWTON_DEGREE} is supported"
+            ));
+        }
+
+        let mut c_struct = NewtonParam {
+            coeffs: [ZERO; MAX_NEWTON_DEGREE + 1],
+            roots: [ZERO; MAX_NEWTON_DEGREE],
+            degree: degree as i32,
+            relaxation: self.relaxation,
+            nova: self.nova as i32,
+            start: self.start,
+            tolerance: self.tolerance,
+        };
+        c_struct.coeffs[..=degree].copy_from_slice(coeffs);
+        c_struct.roots[..degree].copy_from_slice(&durand_kerner(coeffs));
+        Ok(c_struct)
+    }
+}
+
+impl EguiInspect for NewtonParamUI {
+    fn inspect(&self, _label: &str, _ui: &mut egui::Ui) {
+        todo!()
+    }
+
+    fn inspect_mut(&mut self, label: &str, ui: &mut egui::Ui) {
+        ui.label(label);
+        for (k, a) in self.coeffs.iter_mut().enumerate().rev() {
+            ui.horizontal(|ui| {
+                ui.add(DragValue::new(&mut a.re).speed(0.01).prefix("re "));
+                ui.add(DragValue::new(&mut a.im).speed(0.01).prefix("im "));
+                ui.label(match k {
+                    0 => "1".to_string(),
+                    1 => "z".to_string(),
+                    _ => format!("z^{k}"),
+                });
+            });
+        }
+        ui.horizontal(|ui| {
+            let can_raise = self.coeffs.len() <= MAX_NEWTON_DEGREE;
+            if ui
+                .add_enabled(can_raise, Button::new("Raise degree"))
+                .clicked()
+            {
+                self.coeffs.push(ZERO);
+            }
+            let can_lower = self.coeffs.len() > 2;
+            if ui
+                .add_enabled(can_lower, Button::new("Lower degree"))
+                .clicked()
+            {
+                self.coeffs.pop();
+            }
+        });
+        match self.get_c_struct() {
+            Ok(c_struct) => {
+                let roots: Vec<String> = c_struct.roots[..c_struct.degree as usize]
+                    .iter()
+                    .map(|r| format!("{:.4}{:+.4}i", r.re, r.im))
+                    .collect();
+                ui.label(format!("roots: {}", roots.join(", ")));
+            }
+            Err(err) => {
+                ui.colored_label(ui.visuals().error_fg_color, err);
+            }
+        }
+
+        self.relaxation.inspect_mut("relaxation", ui);
+        ui.checkbox(&mut self.nova, "nova");
+        if self.nova {
+            self.start.inspect_mut("start", ui);
+        }
+        ui.add(
+            egui::Slider::new(&mut self.tolerance, 1e-12..=1e-2)
+                .logarithmic(true)
+                .text("tolerance"),
+        );
+    }
+}
+
+/// Colours of the root basins, darkened the more iterations a pixel took to converge
+#[derive(Clone, PartialEq, EguiInspect, Serialize, Deserialize)]
+pub struct BasinColoring {
+    /// sampled evenly, a colour per root
+    pub palette: Palette,
+    #[inspect(log_slider, min = 0.1, max = 100.0)]
+    pub shading: f64,
+}
+
+impl Default for BasinColoring {
+    fn default() -> Self {
+        let hues = [
+            [230, 60, 50],
+            [240, 200, 40],
+            [60, 180, 80],
+            [40, 190, 220],
+            [50, 80, 210],
+            [190, 60, 200],
+        ];
+        let last = (hues.len() - 1) as f64;
+        let stops = hues
+            .into_iter()
+            .enumerate()
+            .map(|(k, color)| ColorStop {
+                pos: k as f64 / last,
+                color,
+            })
+            .collect();
+        Self {
+            palette: Palette::from_stops(stops, Interpolation::OkLab),
+            shading: 4.0,
+        }
+    }
+}
+
+impl BasinColoring {
+    /// One rgb row per root
+    pub fn colors(&self, n_roots: usize) -> Array2<u8> {
+        let mut colors = Array2::zeros((n_roots, 3));
+        for (k, mut row) in colors.outer_iter_mut().enumerate() {
+            let color = self.palette.sample(k as f64 / n_roots as f64);
+            row.as_slice_mut().unwrap().copy_from_slice(&color);
+        }
+        colors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(re: f64, im: f64) -> Complex {
+        Complex { re, im }
+    }
+
+    /// Every expected root has a found one within `tol`, and all found ones are finite
+    fn assert_roots(found: &[Complex], expected: &[Complex], tol: f64) {
+        assert_eq!(found.len(), expected.len());
+        assert!(found.iter().all(|r| r.re.is_finite() && r.im.is_finite()));
+        for &root in expected {
+            assert!(
+                found.iter().any(|&r| (r - root).norm_sqr().sqrt() < tol),
+                "no root near {} {}",
+                root.re,
+                root.im
+            );
+        }
+    }
+
+    #[test]
+    fn cube_roots_of_unity() {
+        let half_sqrt3 = 3f64.sqrt() / 2.0;
+        let roots = durand_kerner(&[c(-1.0, 0.0), ZERO, ZERO, ONE]);
+        assert_roots(
+            &roots,
+            &[ONE, c(-0.5, half_sqrt3), c(-0.5, -half_sqrt3)],
+            1e-10,
+        );
+    }
+
+    #[test]
+    fn non_monic() {
+        // 2(z - 2)(z + 3i)
+        let roots = durand_kerner(&[c(0.0, -12.0), c(-4.0, 6.0), c(2.0, 0.0)]);
+        assert_roots(&roots, &[c(2.0, 0.0), c(0.0, -3.0)], 1e-10);
+    }
+
+    #[test]
+    fn repeated_root() {
+        // (z - 1)^2, converging only linearly on the double root
+        let roots = durand_kerner(&[ONE, c(-2.0, 0.0), ONE]);
+        assert_roots(&roots, &[ONE, ONE], 1e-4);
+    }
+}
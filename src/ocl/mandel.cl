@@ -36,7 +36,10 @@ __kernel void escape_iter(__global int *res_g,
 
     Complex_t _c = param.mandel ? p : param.c;
 
-    res_g[i*M+j] = _escape_iter(p, _c, param.MAXITER);
+    int period;
+    res_g[i*M+j] = _escape_iter(p, _c, param.MAXITER, param.formula,
+                                _plane_interior(param.interior, param.view_rect, M),
+                                param.bailout, &period);
 }
 
 __kernel void escape_iter_fpn(__global FPN *res_g,
@@ -52,7 +55,77 @@ __kernel void escape_iter_fpn(__global FPN *res_g,
 
     Complex_t _c = param.mandel ? p : param.c;
 
-    res_g[i*M+j] = ((FPN) _escape_iter(p, _c, param.MAXITER))/((FPN) param.MAXITER);
+    int period;
+    int n = _escape_iter(p, _c, param.MAXITER, param.formula,
+                         _plane_interior(param.interior, param.view_rect, M),
+                         param.bailout, &period);
+    res_g[i*M+j] = ((FPN) n)/((FPN) param.MAXITER);
+}
+
+// period of the attracting cycle interior points fall into, 0 for escaping points or when none
+// was found within MAXITER
+__kernel void interior_period(__global FPN *res_g,
+                              FParam_t param)
+{
+    int i = get_global_id(0);
+    int j = get_global_id(1);
+    int N = get_global_size(0);
+    int M = get_global_size(1);
+
+    Complex_t p = {param.view_rect.left + j*(param.view_rect.right-param.view_rect.left)/M,
+                   param.view_rect.bot  + i*(param.view_rect.top  -param.view_rect.bot )/N};
+
+    Complex_t _c = param.mandel ? p : param.c;
+
+    InteriorChecks_t interior = _plane_interior(param.interior, param.view_rect, M);
+    interior.periodicity = 1;
+
+    int period;
+    _escape_iter(p, _c, param.MAXITER, param.formula, interior, param.bailout, &period);
+    res_g[i*M+j] = period;
+}
+
+__kernel void smooth_escape_iter(__global FPN *res_g,
+                                 FParam_t param,
+                                 SmoothParams_t SMOOTH)
+{
+    int i = get_global_id(0);
+    int j = get_global_id(1);
+    int N = get_global_size(0);
+    int M = get_global_size(1);
+
+    Complex_t p = {param.view_rect.left + j*(param.view_rect.right-param.view_rect.left)/M,
+                   param.view_rect.bot  + i*(param.view_rect.top  -param.view_rect.bot )/N};
+
+    Complex_t _c = param.mandel ? p : param.c;
+
+    res_g[i*M+j] = _smooth_escape_iter(p, _c, param.MAXITER, SMOOTH, param.formula,
+                                       _plane_interior(param.interior, param.view_rect, M))
+                   /((FPN) param.MAXITER);
+}
+
+// view_rect is relative to the reference orbit's starting point
+__kernel void perturbed_escape_iter(__global FPN *res_g,
+                                    FParam_t param,
+                                    __global Complex_t *ref_g,
+                                    int ref_len,
+                                    SmoothParams_t SMOOTH,
+                                    int smooth)
+{
+    int i = get_global_id(0);
+    int j = get_global_id(1);
+    int N = get_global_size(0);
+    int M = get_global_size(1);
+
+    Complex_t dp = {param.view_rect.left + j*(param.view_rect.right-param.view_rect.left)/M,
+                    param.view_rect.bot  + i*(param.view_rect.top  -param.view_rect.bot )/N};
+
+    // mandel pixel orbits are the reference (from 0) shifted by one
+    Complex_t dc = param.mandel ? dp : (Complex_t){FZERO, FZERO};
+    int m = param.mandel ? 1 : 0;
+
+    res_g[i*M+j] = _perturbed_escape_iter(dp, dc, ref_g, ref_len, m, param.MAXITER,
+                                          SMOOTH, smooth)/((FPN) param.MAXITER);
 }
 
 __kernel void min_prox(__global FPN *res_g,
@@ -69,7 +142,7 @@ __kernel void min_prox(__global FPN *res_g,
 
     Complex_t _c = param.mandel ? p : param.c;
 
-    res_g[i*M+j] = _minprox(p, _c, param.MAXITER, PROXTYPE);
+    res_g[i*M+j] = _minprox(p, _c, param.MAXITER, PROXTYPE, param.formula, param.bailout);
 }
 
 __kernel void orbit_trap(__global Complex_t *res_g,
@@ -86,7 +159,7 @@ __kernel void orbit_trap(__global Complex_t *res_g,
 
     Complex_t _c = param->mandel ? p : param->c;
 
-    res_g[i*M+j] = _orbit_trap(p, _c, *trap, param->MAXITER);
+    res_g[i*M+j] = _orbit_trap(p, _c, *trap, param->MAXITER, param->formula);
 }
 
 __kernel void orbit_trap_re(__global FPN       *res_g,
@@ -103,7 +176,7 @@ __kernel void orbit_trap_re(__global FPN       *res_g,
 
     Complex_t _c = param.mandel ? p : param.c;
 
-    res_g[i*M+j] = _orbit_trap(p, _c, trap, param.MAXITER).re;
+    res_g[i*M+j] = _orbit_trap(p, _c, trap, param.MAXITER, param.formula).re;
 }
 
 __kernel void orbit_trap_im(__global FPN       *res_g,
@@ -120,7 +193,28 @@ __kernel void orbit_trap_im(__global FPN       *res_g,
 
     Complex_t _c = param.mandel ? p : param.c;
 
-    res_g[i*M+j] = _orbit_trap(p, _c, trap, param.MAXITER).im;
+    res_g[i*M+j] = _orbit_trap(p, _c, trap, param.MAXITER, param.formula).im;
+}
+
+// output 0 the trap distance, 1 and 2 the re and im of the hit point uv
+__kernel void shape_trap(__global FPN *res_g,
+                         FParam_t param,
+                         ShapeTrap_t TRAP,
+                         int output)
+{
+    int i = get_global_id(0);
+    int j = get_global_id(1);
+    int N = get_global_size(0);
+    int M = get_global_size(1);
+
+    Complex_t p = {param.view_rect.left + j*(param.view_rect.right-param.view_rect.left)/M,
+                   param.view_rect.bot  + i*(param.view_rect.top  -param.view_rect.bot )/N};
+
+    Complex_t _c = param.mandel ? p : param.c;
+
+    Complex_t uv;
+    FPN d = _shape_trap(p, _c, param.MAXITER, param.formula, param.bailout, TRAP, &uv);
+    res_g[i*M+j] = output == 0 ? d : (output == 1 ? uv.re : uv.im);
 }
 
 __kernel void map_img   (__global Complex_t *res_g, // result of orbit trap
@@ -243,6 +337,27 @@ __kernel void pack_norm(__global FPN     *res1_g,
 
 }
 
+__kernel void distance_estimate(__global FPN *res_g,
+                                FParam_t param,
+                                DistanceParams_t DIST)
+{
+    int i = get_global_id(0);
+    int j = get_global_id(1);
+    int N = get_global_size(0);
+    int M = get_global_size(1);
+
+    Complex_t p = {param.view_rect.left + j*(param.view_rect.right-param.view_rect.left)/M,
+                   param.view_rect.bot  + i*(param.view_rect.top  -param.view_rect.bot )/N};
+
+    Complex_t _c = param.mandel ? p : param.c;
+
+    FPN pixel = (param.view_rect.right - param.view_rect.left) / M;
+    FPN d = _distance_estimate(p, _c, param.mandel, param.MAXITER, DIST, param.formula);
+    d /= pixel * DIST.thickness;
+
+    res_g[i*M+j] = d < FONE ? d : FONE;
+}
+
 __kernel void map_sines(__global FPN     *res_g,
                         __global Pixel_t *img_g,
                         Freqs_t freqs)
@@ -259,3 +374,169 @@ __kernel void map_sines(__global FPN     *res_g,
                           127*(sin(res_g[fi]*freqs.f3)+1)};
 
 }
+
+__kernel void map_palette(__global FPN     *res_g,
+                          __global Pixel_t *lut_g,
+                          int               lut_len,
+                          PaletteMapping_t  MAPPING,
+                          __global Pixel_t *img_g)
+{
+    int i = get_global_id(0);
+    int j = get_global_id(1);
+    int N = get_global_size(0);
+    int M = get_global_size(1);
+
+    int fi = i*M + j;
+
+    FPN u = _palette_coord(res_g[fi], MAPPING);
+    img_g[fi] = lut_g[min((int) (u * lut_len), lut_len - 1)];
+}
+
+__kernel void newton_root(__global FPN *res_g,
+                          FParam_t param,
+                          NewtonParam_t NEWTON)
+{
+    int i = get_global_id(0);
+    int j = get_global_id(1);
+    int N = get_global_size(0);
+    int M = get_global_size(1);
+
+    Complex_t p = {param.view_rect.left + j*(param.view_rect.right-param.view_rect.left)/M,
+                   param.view_rect.bot  + i*(param.view_rect.top  -param.view_rect.bot )/N};
+
+    // plain newton ignores c, mandel mode nova varies it from a fixed start
+    Complex_t _c = param.mandel ? p : param.c;
+    Complex_t z = (NEWTON.nova && param.mandel) ? NEWTON.start : p;
+
+    int root;
+    _newton(z, _c, param.MAXITER, NEWTON, &root);
+    res_g[i*M+j] = ((FPN) (root + 1))/((FPN) NEWTON.degree);
+}
+
+__kernel void newton_iters(__global FPN *res_g,
+                           FParam_t param,
+                           NewtonParam_t NEWTON)
+{
+    int i = get_global_id(0);
+    int j = get_global_id(1);
+    int N = get_global_size(0);
+    int M = get_global_size(1);
+
+    Complex_t p = {param.view_rect.left + j*(param.view_rect.right-param.view_rect.left)/M,
+                   param.view_rect.bot  + i*(param.view_rect.top  -param.view_rect.bot )/N};
+
+    Complex_t _c = param.mandel ? p : param.c;
+    Complex_t z = (NEWTON.nova && param.mandel) ? NEWTON.start : p;
+
+    int root;
+    res_g[i*M+j] = ((FPN) _newton(z, _c, param.MAXITER, NEWTON, &root))/((FPN) param.MAXITER);
+}
+
+// basin (field 1, as from newton_root) coloured by the matching row of colors_g,
+// darkened by the iterations taken (field 2, as from newton_iters)
+__kernel void map_basins(__global FPN     *root_g,
+                         __global FPN     *iters_g,
+                         __global Pixel_t *colors_g,
+                         int               n_roots,
+                         FPN               shading,
+                         __global Pixel_t *img_g)
+{
+    int i = get_global_id(0);
+    int j = get_global_id(1);
+    int N = get_global_size(0);
+    int M = get_global_size(1);
+
+    int fi = i*M + j;
+
+    int k = (int) round(root_g[fi] * n_roots) - 1;
+    if (k < 0) {
+        img_g[fi] = (Pixel_t){0, 0, 0};
+        return;
+    }
+
+    Pixel_t color = colors_g[min(k, n_roots - 1)];
+    FPN b = pow(FONE - iters_g[fi], shading);
+    img_g[fi] = (Pixel_t){b*color.r, b*color.g, b*color.b};
+}
+
+// random starting points over the [-2, 2] square, orbits accumulated into
+// histograms of the view rather than one value per pixel
+__kernel void buddhabrot(__global int *hist1_g,
+                         __global int *hist2_g,
+                         __global int *hist3_g,
+                         FParam_t param,
+                         BuddhabrotParam_t BUDDHA,
+                         uint seed)
+{
+    int i = get_global_id(0);
+    int j = get_global_id(1);
+    int N = get_global_size(0);
+    int M = get_global_size(1);
+
+    uint state = _hash(seed ^ _hash(i*M + j));
+
+    for (int s = 0; s < BUDDHA.samples; s++) {
+        Complex_t p;
+        p.re = 4*_uniform(&state) - 2;
+        p.im = 4*_uniform(&state) - 2;
+
+        Complex_t _c = param.mandel ? p : param.c;
+
+        _buddhabrot(p, _c, param.view_rect, N, M, BUDDHA, param.formula,
+                    param.interior, param.bailout, hist1_g, hist2_g, hist3_g);
+    }
+}
+
+__kernel void map_histogram(__global int *hist_g,
+                            __global FPN *res_g,
+                            FPN scale)
+{
+    int i = get_global_id(0);
+    int j = get_global_id(1);
+    int N = get_global_size(0);
+    int M = get_global_size(1);
+
+    res_g[i*M+j] = FONE - exp(-scale * hist_g[i*M+j]);
+}
+
+// the view's [-2, 2] square covers the logistic map's rates [0, 4] for a (re) and b (im)
+__kernel void lyapunov(__global FPN *res_g,
+                       FParam_t param,
+                       LyapunovParam_t LYAPUNOV)
+{
+    int i = get_global_id(0);
+    int j = get_global_id(1);
+    int N = get_global_size(0);
+    int M = get_global_size(1);
+
+    Complex_t p = {param.view_rect.left + j*(param.view_rect.right-param.view_rect.left)/M,
+                   param.view_rect.bot  + i*(param.view_rect.top  -param.view_rect.bot )/N};
+
+    res_g[i*M+j] = _lyapunov(p.re + 2, p.im + 2, param.MAXITER, LYAPUNOV);
+}
+
+// the film spans the width of SCENE.frame, of which view_rect may be a part or a shifted copy
+__kernel void ray_march(__global Pixel_t *img_g,
+                        FParam_t param,
+                        SceneParam_t SCENE)
+{
+    int i = get_global_id(0);
+    int j = get_global_id(1);
+    int N = get_global_size(0);
+    int M = get_global_size(1);
+
+    Complex_t p = {param.view_rect.left + j*(param.view_rect.right-param.view_rect.left)/M,
+                   param.view_rect.bot  + i*(param.view_rect.top  -param.view_rect.bot )/N};
+
+    // first image row at the top
+    Box_t f = SCENE.frame;
+    FPN half_width = (f.right - f.left)/2;
+    FPN u = (p.re - (f.left + f.right)/2)/half_width;
+    FPN v = ((f.bot + f.top)/2 - p.im)/half_width;
+
+    Vec3_t film = v3_add(v3_scale(SCENE.right, u), v3_scale(SCENE.up, v));
+    Vec3_t dir = v3_norm(v3_add(SCENE.forward, v3_scale(film, SCENE.fov_scale)));
+
+    Vec3_t rgb = _ray_march(SCENE.eye, dir, SCENE);
+    img_g[i*M+j] = (Pixel_t){255*rgb.x, 255*rgb.y, 255*rgb.z};
+}
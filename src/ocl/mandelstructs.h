@@ -26,12 +26,35 @@ typedef struct Pixel {
   unsigned char b;
 } Pixel_t;
 
+typedef struct Formula {
+  int id;    // 0 custom f, 1 mandelbrot, 2 burning ship, 3 tricorn,
+             // 4 multibrot, 5 celtic, 6 buffalo
+  FPN power; // multibrot exponent
+  int flip;  // burning ship orientation
+} Formula_t;
+
+typedef struct InteriorChecks {
+  int bulbs;       // main cardioid and period 2 bulb, z^2+c in mandel mode only
+  int periodicity; // Brent cycle detection
+  FPN tolerance;   // distance in pixels at which an orbit point counts as
+                   // revisited, see _plane_interior
+} InteriorChecks_t;
+
+typedef struct Bailout {
+  FPN radius;
+  int norm; // 0 euclidean, 1 square (max of |re| and |im|), 2 real (|re|),
+            // 3 manhattan (|re|+|im|), 4 custom bailout(z)
+} Bailout_t;
+
 typedef struct FParam {
   // General fract iter params
   int mandel;  // mandel or julia
   Complex_t c; // not given when mandel selected
   Box_t view_rect;
   int MAXITER;
+  Formula_t formula;
+  InteriorChecks_t interior;
+  Bailout_t bailout;
 } FParam_t;
 
 typedef struct ProxType {
@@ -50,3 +73,94 @@ typedef struct Freqs {
   FPN f2;
   FPN f3;
 } Freqs_t;
+
+typedef struct SmoothParams {
+  FPN bailout;
+  FPN degree;
+  unsigned char estimate_degree;
+} SmoothParams_t;
+
+typedef struct DistanceParams {
+  FPN bailout;
+  FPN thickness; // distance in pixels mapped to 1
+} DistanceParams_t;
+
+#define MAX_NEWTON_DEGREE 8
+
+typedef struct NewtonParam {
+  Complex_t coeffs[MAX_NEWTON_DEGREE + 1]; // coefficient of z^k at k
+  Complex_t roots[MAX_NEWTON_DEGREE];      // found on the host
+  int degree;
+  Complex_t relaxation;
+  int nova;        // adds c after each step
+  Complex_t start; // starting point of nova in mandel mode
+  FPN tolerance;
+} NewtonParam_t;
+
+typedef struct BuddhabrotParam {
+  int max_iter[3]; // per histogram
+  int anti;        // splat the orbits that stay bounded instead
+  int samples;     // per work item and pass
+} BuddhabrotParam_t;
+
+typedef struct LyapunovParam {
+  unsigned int sequence; // bit k set when the k-th rate of the sequence is b
+  int length;
+  int warmup;    // iterations before the exponent is accumulated
+  FPN contrast;
+} LyapunovParam_t;
+
+#define MAX_TRAP_SHAPES 8
+
+typedef struct TrapShape {
+  int kind;  // 0 point, 1 unit circle, 2 segment from -1 to 1, 3 cross of unit
+             // arms, 4 regular polygon of circumradius 1, 5 custom trap_sdf(z)
+  int sides; // of the polygon
+  Complex_t offset;
+  FPN angle; // radians
+  FPN scale;
+} TrapShape_t;
+
+typedef struct ShapeTrap {
+  TrapShape_t shapes[MAX_TRAP_SHAPES];
+  int n_shapes;
+  int mode;      // 0 min distance, 1 average distance, 2 first hit
+  FPN threshold; // distance counting as a hit
+} ShapeTrap_t;
+
+typedef struct Vec3 {
+  FPN x;
+  FPN y;
+  FPN z;
+} Vec3_t;
+
+typedef struct SceneParam {
+  Box_t frame; // full view, whose width the film spans
+  Vec3_t eye;
+  Vec3_t forward; // orthonormal camera basis
+  Vec3_t right;
+  Vec3_t up;
+  FPN fov_scale; // tan of half the horizontal field of view
+  int shape;     // 0 mandelbulb, 1 mandelbox
+  FPN power;     // mandelbulb exponent
+  FPN box_scale;
+  FPN min_radius; // mandelbox sphere fold
+  FPN fold_limit; // mandelbox box fold
+  int iters;      // of the distance estimate
+  int max_steps;
+  FPN detail; // hit distance per unit of distance travelled
+  FPN max_dist;
+  Vec3_t light_dir; // towards the light, unit length
+  FPN softness;     // larger is harder shadows
+  FPN ao;
+  FPN specular;
+  FPN shininess;
+  ProxType_t trap;
+  Freqs_t trap_freqs;
+} SceneParam_t;
+
+typedef struct PaletteMapping {
+  FPN scale;
+  FPN offset;
+  int repeat_mode; // 0 clamp, 1 repeat, 2 mirror
+} PaletteMapping_t;
@@ -15,6 +15,13 @@ Complex_t complex_add(Complex_t a, Complex_t b) {
   return c;
 }
 
+Complex_t complex_sub(Complex_t a, Complex_t b) {
+  Complex_t c;
+  c.re = a.re - b.re;
+  c.im = a.im - b.im;
+  return c;
+}
+
 Complex_t complex_mult(Complex_t a, Complex_t b) {
   Complex_t c;
   c.re = a.re * b.re - a.im * b.im;
@@ -22,6 +29,14 @@ Complex_t complex_mult(Complex_t a, Complex_t b) {
   return c;
 }
 
+Complex_t complex_div(Complex_t a, Complex_t b) {
+  FPN d = b.re * b.re + b.im * b.im;
+  Complex_t c;
+  c.re = (a.re * b.re + a.im * b.im) / d;
+  c.im = (a.im * b.re - a.re * b.im) / d;
+  return c;
+}
+
 Complex_t complex_pow(Complex_t z, int n) {
   Complex_t p = z;
   for (int i = 1; i < n; i++) {
@@ -35,8 +50,110 @@ Complex_t complex_pow(Complex_t z, int n) {
 inline Complex_t f(Complex_t z, Complex_t c) {
   return complex_add(complex_pow(z, 2), c);
 }
+
+#define CUSTOM_DF
+inline Complex_t df_dz(Complex_t z, Complex_t c) {
+  return complex_add(z, z);
+}
+
+inline Complex_t df_dc(Complex_t z, Complex_t c) {
+  return (Complex_t){FONE, FZERO};
+}
+
+#define CUSTOM_BAILOUT
+inline int bailout(Complex_t z) { return z.re * z.re + z.im * z.im > 4; }
 //<<
 
+#ifndef CUSTOM_TRAP
+inline FPN trap_sdf(Complex_t z) { return sqrt(z.re * z.re + z.im * z.im); }
+#endif
+
+#ifndef CUSTOM_BAILOUT
+inline int bailout(Complex_t z) { return z.re * z.re + z.im * z.im > 4; }
+#endif
+
+#ifndef CUSTOM_DF
+// derivatives of f by central differences along the real axis, fine for analytic f
+inline FPN _df_step(Complex_t z) {
+  return 1e-6 * (FONE + _abs(z.re) + _abs(z.im));
+}
+
+inline Complex_t df_dz(Complex_t z, Complex_t c) {
+  FPN h = _df_step(z);
+  Complex_t fp = f((Complex_t){z.re + h, z.im}, c);
+  Complex_t fm = f((Complex_t){z.re - h, z.im}, c);
+  return (Complex_t){(fp.re - fm.re) / (2 * h), (fp.im - fm.im) / (2 * h)};
+}
+
+inline Complex_t df_dc(Complex_t z, Complex_t c) {
+  FPN h = _df_step(c);
+  Complex_t fp = f(z, (Complex_t){c.re + h, c.im});
+  Complex_t fm = f(z, (Complex_t){c.re - h, c.im});
+  return (Complex_t){(fp.re - fm.re) / (2 * h), (fp.im - fm.im) / (2 * h)};
+}
+#endif
+
+Complex_t complex_powf(Complex_t z, FPN p)
+// principal branch of z^p for real p
+{
+  FPN r = sqrt(z.re * z.re + z.im * z.im);
+  if (r == 0) {
+    return z;
+  }
+  FPN rp = pow(r, p);
+  FPN th = atan2(z.im, z.re) * p;
+  return (Complex_t){rp * cos(th), rp * sin(th)};
+}
+
+Complex_t _iterate(Complex_t z, Complex_t c, Formula_t FORMULA)
+// one step of the selected built in formula, or of f
+{
+  Complex_t w;
+  switch (FORMULA.id) {
+  case 1:
+    return complex_add(complex_mult(z, z), c);
+  case 2:
+    w = (Complex_t){_abs(z.re), FORMULA.flip ? -_abs(z.im) : _abs(z.im)};
+    return complex_add(complex_mult(w, w), c);
+  case 3:
+    w = (Complex_t){z.re, -z.im};
+    return complex_add(complex_mult(w, w), c);
+  case 4:
+    return complex_add(complex_powf(z, FORMULA.power), c);
+  case 5:
+    w = complex_mult(z, z);
+    w.re = _abs(w.re);
+    return complex_add(w, c);
+  case 6:
+    w = complex_mult(z, z);
+    w = (Complex_t){_abs(w.re), _abs(w.im)};
+    return complex_add(w, c);
+  default:
+    return f(z, c);
+  }
+}
+
+Complex_t _iterate_df_dz(Complex_t z, Complex_t c, Formula_t FORMULA)
+// derivative of a step w.r.t. z, the formulas folding z (by abs or conjugation)
+// are not analytic and get the modulus of the quadratic's derivative instead
+{
+  switch (FORMULA.id) {
+  case 0:
+    return df_dz(z, c);
+  case 1:
+    return complex_add(z, z);
+  case 4:
+    return complex_mult((Complex_t){FORMULA.power, FZERO},
+                        complex_powf(z, FORMULA.power - 1));
+  default:
+    return (Complex_t){2 * sqrt(z.re * z.re + z.im * z.im), FZERO};
+  }
+}
+
+Complex_t _iterate_df_dc(Complex_t z, Complex_t c, Formula_t FORMULA) {
+  return FORMULA.id == 0 ? df_dc(z, c) : (Complex_t){FONE, FZERO};
+}
+
 int in_circle(Complex_t z, Complex_t z0, FPN r) {
   FPN dre = z.re - z0.re;
   FPN dim = z.im - z0.im;
@@ -47,8 +164,22 @@ inline int in_box(Complex_t z, Box_t b) {
   return z.re > b.left && z.re < b.right && z.im > b.bot && z.im < b.top;
 }
 
-inline int in_bounds(Complex_t z) {
-  return in_circle(z, (Complex_t){FZERO, FZERO}, 2);
+int in_bounds(Complex_t z, Bailout_t BAILOUT)
+// whether z has yet to escape, under the selected norm
+{
+  FPN r = BAILOUT.radius;
+  switch (BAILOUT.norm) {
+  case 1:
+    return _abs(z.re) < r && _abs(z.im) < r;
+  case 2:
+    return _abs(z.re) < r;
+  case 3:
+    return _abs(z.re) + _abs(z.im) < r;
+  case 4:
+    return !bailout(z);
+  default:
+    return in_circle(z, (Complex_t){FZERO, FZERO}, r);
+  }
 }
 
 FPN proximity(Complex_t z, ProxType_t PROXTYPE)
@@ -69,25 +200,172 @@ FPN proximity(Complex_t z, ProxType_t PROXTYPE)
   return res;
 }
 
-int _escape_iter(Complex_t z, Complex_t c, int MAXITER) {
+int _bulb_period(Complex_t c)
+// 1 in the main cardioid, 2 in the period 2 bulb, 0 when not known
+{
+  FPN x = c.re - 0.25;
+  FPN y2 = c.im * c.im;
+  FPN q = x * x + y2;
+  if (q * (q + x) <= 0.25 * y2) {
+    return 1;
+  }
+  if ((c.re + 1) * (c.re + 1) + y2 <= 0.0625) {
+    return 2;
+  }
+  return 0;
+}
+
+InteriorChecks_t _plane_interior(InteriorChecks_t INTERIOR, Box_t view, int M)
+// the tolerance is set in pixels, this has it in the plane for a view M pixels wide
+{
+  INTERIOR.tolerance *= (view.right - view.left) / M;
+  return INTERIOR;
+}
+
+int _brent_step(Complex_t z, Complex_t *check, int *power, int *lam, FPN tol2)
+// Brent's cycle detection, compares z against an orbit point saved at
+// doubling intervals, returns the period once it is revisited, 0 until then
+{
+  *lam += 1;
+  FPN dre = z.re - check->re;
+  FPN dim = z.im - check->im;
+  if (dre * dre + dim * dim < tol2) {
+    return *lam;
+  }
+  if (*lam == *power) {
+    *check = z;
+    *power *= 2;
+    *lam = 0;
+  }
+  return 0;
+}
+
+int _escape_iter(Complex_t z, Complex_t c, int MAXITER, Formula_t FORMULA,
+                 InteriorChecks_t INTERIOR, Bailout_t BAILOUT, int *period)
+// interior points found by the checks exit early with MAXITER, and their period
+{
+  *period = INTERIOR.bulbs ? _bulb_period(c) : 0;
+  if (*period) {
+    return MAXITER;
+  }
+
+  FPN tol2 = INTERIOR.tolerance * INTERIOR.tolerance;
+  Complex_t check = z;
+  int power = 1;
+  int lam = 0;
 
   int i = 0;
-  while (i < MAXITER && in_bounds(z)) {
-    z = f(z, c);
+  while (i < MAXITER && in_bounds(z, BAILOUT)) {
+    z = _iterate(z, c, FORMULA);
     i += 1;
+    if (INTERIOR.periodicity) {
+      *period = _brent_step(z, &check, &power, &lam, tol2);
+      if (*period) {
+        return MAXITER;
+      }
+    }
   }
 
   return i;
 }
 
-FPN _minprox(Complex_t z, Complex_t c, int MAXITER, ProxType_t PROXTYPE)
+FPN _smooth_count(int i, FPN norm, FPN prev_norm, SmoothParams_t SMOOTH)
+// fractional part from how far past the bailout the last step landed,
+// norm and prev_norm are the squared moduli of the last two orbit points
+{
+  FPN d = SMOOTH.degree;
+  if (SMOOTH.estimate_degree && prev_norm > 1) {
+    // far from the origin |z_n| ~ |z_{n-1}|^d
+    FPN d_est = log(norm) / log(prev_norm);
+    if (d_est > 1) {
+      d = d_est;
+    }
+  }
+
+  FPN nu = log(0.5 * log(norm) / log(SMOOTH.bailout)) / log(d);
+  return i + 1 - nu;
+}
+
+FPN _smooth_escape_iter(Complex_t z, Complex_t c, int MAXITER,
+                        SmoothParams_t SMOOTH, Formula_t FORMULA,
+                        InteriorChecks_t INTERIOR)
+// normalised iteration count
+{
+  if (INTERIOR.bulbs && _bulb_period(c)) {
+    return MAXITER;
+  }
+
+  FPN r2 = SMOOTH.bailout * SMOOTH.bailout;
+  FPN norm = z.re * z.re + z.im * z.im;
+  FPN prev_norm = norm;
+  FPN tol2 = INTERIOR.tolerance * INTERIOR.tolerance;
+  Complex_t check = z;
+  int power = 1;
+  int lam = 0;
+
+  int i = 0;
+  while (i < MAXITER && norm < r2) {
+    z = _iterate(z, c, FORMULA);
+    prev_norm = norm;
+    norm = z.re * z.re + z.im * z.im;
+    i += 1;
+    if (INTERIOR.periodicity && _brent_step(z, &check, &power, &lam, tol2)) {
+      return MAXITER;
+    }
+  }
+
+  if (i == MAXITER) {
+    return MAXITER;
+  }
+
+  return _smooth_count(i, norm, prev_norm, SMOOTH);
+}
+
+FPN _perturbed_escape_iter(Complex_t dz, Complex_t dc,
+                           __global Complex_t *ref, int ref_len, int m,
+                           int MAXITER, SmoothParams_t SMOOTH, int smooth)
+// escape (or smooth) iteration count of z = ref[m] + dz, only dz is iterated,
+// for the built in z^2+c only
+{
+  FPN r2 = smooth ? SMOOTH.bailout * SMOOTH.bailout : 4;
+  Complex_t z = complex_add(ref[m], dz);
+  FPN norm = z.re * z.re + z.im * z.im;
+  FPN prev_norm = norm;
+
+  int i = 0;
+  while (i < MAXITER && norm < r2) {
+    // glitch (z small relative to its offset) or end of reference,
+    // rebase onto the start of the reference orbit
+    if (m == ref_len - 1 || norm < dz.re * dz.re + dz.im * dz.im) {
+      dz = complex_sub(z, ref[0]);
+      m = 0;
+    }
+    // (Z + dz)^2 + c - (Z^2 + C) = (2Z + dz)dz + dc
+    dz = complex_add(complex_mult(complex_add(complex_add(ref[m], ref[m]), dz), dz),
+                     dc);
+    m += 1;
+    i += 1;
+    z = complex_add(ref[m], dz);
+    prev_norm = norm;
+    norm = z.re * z.re + z.im * z.im;
+  }
+
+  if (!smooth || i == MAXITER) {
+    return i;
+  }
+
+  return _smooth_count(i, norm, prev_norm, SMOOTH);
+}
+
+FPN _minprox(Complex_t z, Complex_t c, int MAXITER, ProxType_t PROXTYPE,
+             Formula_t FORMULA, Bailout_t BAILOUT)
 // more of a distance field?
 {
 
   int i = 0;
   FPN dist = proximity(z, PROXTYPE);
-  while (i < MAXITER && in_bounds(z)) {
-    z = f(z, c);
+  while (i < MAXITER && in_bounds(z, BAILOUT)) {
+    z = _iterate(z, c, FORMULA);
     dist = _min(dist, proximity(z, PROXTYPE));
     i += 1;
   }
@@ -95,7 +373,8 @@ FPN _minprox(Complex_t z, Complex_t c, int MAXITER, ProxType_t PROXTYPE)
   return dist;
 }
 
-Complex_t _orbit_trap(Complex_t z, Complex_t c, Box_t b, int MAXITER)
+Complex_t _orbit_trap(Complex_t z, Complex_t c, Box_t b, int MAXITER,
+                      Formula_t FORMULA)
 // returns UV coords in given box
 {
   Complex_t res = {-b.left, -b.bot};
@@ -103,7 +382,7 @@ Complex_t _orbit_trap(Complex_t z, Complex_t c, Box_t b, int MAXITER)
   int i = 0;
   while (i < MAXITER) {
     i += 1;
-    z = f(z, c);
+    z = _iterate(z, c, FORMULA);
     if (in_box(z, b)) {
       res = complex_add(res, z);
       res.re /= (b.right - b.left);
@@ -114,3 +393,435 @@ Complex_t _orbit_trap(Complex_t z, Complex_t c, Box_t b, int MAXITER)
 
   return (Complex_t){FZERO, FZERO};
 }
+
+FPN _segment_distance(Complex_t z, Complex_t a, Complex_t b) {
+  Complex_t pa = complex_sub(z, a);
+  Complex_t ba = complex_sub(b, a);
+  FPN h = clamp((pa.re * ba.re + pa.im * ba.im) / (ba.re * ba.re + ba.im * ba.im),
+                FZERO, FONE);
+  FPN dre = pa.re - ba.re * h;
+  FPN dim = pa.im - ba.im * h;
+  return sqrt(dre * dre + dim * dim);
+}
+
+FPN _polygon_distance(Complex_t z, int sides)
+// to the outline of the regular polygon with a vertex at 1
+{
+  FPN d = 1000 * FONE;
+  FPN step = 2 * M_PI / sides;
+  Complex_t a = {FONE, FZERO};
+  for (int k = 1; k <= sides; k++) {
+    Complex_t b = {cos(k * step), sin(k * step)};
+    d = _min(d, _segment_distance(z, a, b));
+    a = b;
+  }
+  return d;
+}
+
+FPN _shape_distance(Complex_t z, TrapShape_t SHAPE, Complex_t *local)
+// distance from z to a trap shape, local is z in the shape's own frame
+{
+  Complex_t w = complex_sub(z, SHAPE.offset);
+  FPN ca = cos(SHAPE.angle);
+  FPN sa = sin(SHAPE.angle);
+  w = (Complex_t){(ca * w.re + sa * w.im) / SHAPE.scale,
+                  (ca * w.im - sa * w.re) / SHAPE.scale};
+  *local = w;
+
+  FPN d;
+  switch (SHAPE.kind) {
+  case 1:
+    d = _abs(sqrt(w.re * w.re + w.im * w.im) - FONE);
+    break;
+  case 2:
+    d = _segment_distance(w, (Complex_t){-FONE, FZERO}, (Complex_t){FONE, FZERO});
+    break;
+  case 3:
+    d = _min(_segment_distance(w, (Complex_t){-FONE, FZERO}, (Complex_t){FONE, FZERO}),
+             _segment_distance(w, (Complex_t){FZERO, -FONE}, (Complex_t){FZERO, FONE}));
+    break;
+  case 4:
+    d = _polygon_distance(w, SHAPE.sides);
+    break;
+  case 5:
+    d = _abs(trap_sdf(w));
+    break;
+  default:
+    d = sqrt(w.re * w.re + w.im * w.im);
+  }
+  return d * SHAPE.scale;
+}
+
+FPN _shape_trap(Complex_t z, Complex_t c, int MAXITER, Formula_t FORMULA,
+                Bailout_t BAILOUT, ShapeTrap_t TRAP, Complex_t *uv)
+// distance of the orbit to the union of the shapes, by the trap mode, with the
+// trapping point in its shape's frame as uv over the [-1, 1] square (0 when
+// nothing was hit)
+{
+  FPN best = 1e30;
+  FPN sum = FZERO;
+  Complex_t hit = {-FONE, -FONE};
+  Complex_t local;
+
+  int i = 0;
+  while (i < MAXITER && in_bounds(z, BAILOUT)) {
+    z = _iterate(z, c, FORMULA);
+    i += 1;
+
+    FPN d = 1e30;
+    Complex_t nearest = hit;
+    for (int k = 0; k < TRAP.n_shapes; k++) {
+      FPN dk = _shape_distance(z, TRAP.shapes[k], &local);
+      if (dk < d) {
+        d = dk;
+        nearest = local;
+      }
+    }
+
+    sum += d;
+    if (d < best) {
+      best = d;
+      hit = nearest;
+    }
+    if (TRAP.mode == 2 && d < TRAP.threshold) {
+      *uv = (Complex_t){0.5 + 0.5 * hit.re, 0.5 + 0.5 * hit.im};
+      return d / TRAP.threshold;
+    }
+  }
+
+  if (TRAP.mode == 2) {
+    *uv = (Complex_t){FZERO, FZERO};
+    return FONE;
+  }
+  *uv = (Complex_t){0.5 + 0.5 * hit.re, 0.5 + 0.5 * hit.im};
+  return TRAP.mode == 1 ? sum / (i > 0 ? i : 1) : best;
+}
+
+FPN _distance_estimate(Complex_t z, Complex_t c, int mandel, int MAXITER,
+                       DistanceParams_t DIST, Formula_t FORMULA)
+// exterior distance estimate |z|ln|z|/2|dz|, with dz the derivative of the orbit w.r.t. its
+// starting point (and c in mandel mode, where the two coincide), 0 in the interior
+{
+  Complex_t dz = {FONE, FZERO};
+  FPN bailout2 = DIST.bailout * DIST.bailout;
+
+  for (int i = 0; i < MAXITER; i++) {
+    FPN norm = z.re * z.re + z.im * z.im;
+    if (norm > bailout2) {
+      FPN r = sqrt(norm);
+      FPN dr = sqrt(dz.re * dz.re + dz.im * dz.im);
+      return 0.5 * r * log(r) / dr;
+    }
+    dz = complex_mult(_iterate_df_dz(z, c, FORMULA), dz);
+    if (mandel) {
+      dz = complex_add(dz, _iterate_df_dc(z, c, FORMULA));
+    }
+    z = _iterate(z, c, FORMULA);
+  }
+
+  return FZERO;
+}
+
+Complex_t _newton_step(Complex_t z, Complex_t c, NewtonParam_t NEWTON)
+// z - a p(z)/p'(z), plus c for nova
+{
+  // horner, evaluating p and p' together
+  Complex_t p = NEWTON.coeffs[NEWTON.degree];
+  Complex_t dp = {FZERO, FZERO};
+  for (int k = NEWTON.degree - 1; k >= 0; k--) {
+    dp = complex_add(complex_mult(dp, z), p);
+    p = complex_add(complex_mult(p, z), NEWTON.coeffs[k]);
+  }
+
+  z = complex_sub(z, complex_mult(NEWTON.relaxation, complex_div(p, dp)));
+  return NEWTON.nova ? complex_add(z, c) : z;
+}
+
+int _newton(Complex_t z, Complex_t c, int MAXITER, NewtonParam_t NEWTON,
+            int *root)
+// iterations until the step falls below the tolerance, root is set to the index
+// of the nearest root of p then, or -1 when not converged
+{
+  FPN tol2 = NEWTON.tolerance * NEWTON.tolerance;
+
+  for (int i = 0; i < MAXITER; i++) {
+    Complex_t next = _newton_step(z, c, NEWTON);
+    Complex_t d = complex_sub(next, z);
+    z = next;
+    if (d.re * d.re + d.im * d.im < tol2) {
+      FPN best = -1;
+      for (int k = 0; k < NEWTON.degree; k++) {
+        Complex_t dr = complex_sub(z, NEWTON.roots[k]);
+        FPN dist = dr.re * dr.re + dr.im * dr.im;
+        if (best < 0 || dist < best) {
+          best = dist;
+          *root = k;
+        }
+      }
+      return i + 1;
+    }
+  }
+
+  *root = -1;
+  return MAXITER;
+}
+
+FPN _lyapunov(FPN a, FPN b, int MAXITER, LyapunovParam_t LYAPUNOV)
+// exponent of the logistic map x -> r x (1 - x), r following the AB sequence,
+// squashed into (0, 1) with stable (negative) exponents above 0.5
+{
+  FPN x = 0.5;
+  FPN sum = FZERO;
+  int k = 0;
+
+  for (int i = 0; i < LYAPUNOV.warmup + MAXITER; i++) {
+    FPN r = (LYAPUNOV.sequence >> k) & 1 ? b : a;
+    k = k + 1 == LYAPUNOV.length ? 0 : k + 1;
+    if (i >= LYAPUNOV.warmup) {
+      sum += log(_abs(r * (1 - 2 * x)));
+    }
+    x = r * x * (1 - x);
+  }
+
+  FPN exponent = sum / MAXITER;
+  if (isnan(exponent)) {
+    return FZERO;
+  }
+  return FONE / (FONE + exp(exponent * LYAPUNOV.contrast));
+}
+
+inline Vec3_t v3(FPN x, FPN y, FPN z) { return (Vec3_t){x, y, z}; }
+
+inline Vec3_t v3_add(Vec3_t a, Vec3_t b) { return v3(a.x + b.x, a.y + b.y, a.z + b.z); }
+
+inline Vec3_t v3_sub(Vec3_t a, Vec3_t b) { return v3(a.x - b.x, a.y - b.y, a.z - b.z); }
+
+inline Vec3_t v3_scale(Vec3_t a, FPN s) { return v3(a.x * s, a.y * s, a.z * s); }
+
+inline FPN v3_dot(Vec3_t a, Vec3_t b) { return a.x * b.x + a.y * b.y + a.z * b.z; }
+
+inline FPN v3_len(Vec3_t a) { return sqrt(v3_dot(a, a)); }
+
+inline Vec3_t v3_norm(Vec3_t a) { return v3_scale(a, FONE / v3_len(a)); }
+
+FPN _trap_proximity(Vec3_t z, ProxType_t TRAP)
+// proximity of a 3d orbit point, to the unit sphere and the x and y components
+{
+  FPN res = 1000 * FONE;
+  if (TRAP.to_unit_circ) {
+    res = _min(res, v3_dot(z, z));
+  }
+  if (TRAP.to_horizontal) {
+    res = _min(res, _abs(z.x));
+  }
+  if (TRAP.to_vertical) {
+    res = _min(res, _abs(z.y));
+  }
+  return res;
+}
+
+FPN _mandelbulb_de(Vec3_t pos, SceneParam_t SCENE, FPN *trap) {
+  Vec3_t z = pos;
+  FPN dr = FONE;
+  FPN r = v3_len(z);
+
+  for (int i = 0; i < SCENE.iters && r < 2; i++) {
+    *trap = _min(*trap, _trap_proximity(z, SCENE.trap));
+    FPN theta = r > 0 ? acos(z.z / r) * SCENE.power : FZERO;
+    FPN phi = atan2(z.y, z.x) * SCENE.power;
+    dr = pow(r, SCENE.power - 1) * SCENE.power * dr + FONE;
+    FPN zr = pow(r, SCENE.power);
+    z = v3_add(v3_scale(v3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta)), zr),
+               pos);
+    r = v3_len(z);
+  }
+
+  return 0.5 * log(r) * r / dr;
+}
+
+FPN _mandelbox_de(Vec3_t pos, SceneParam_t SCENE, FPN *trap) {
+  Vec3_t z = pos;
+  FPN dr = FONE;
+  FPN min_r2 = SCENE.min_radius * SCENE.min_radius;
+  FPN l = SCENE.fold_limit;
+
+  for (int i = 0; i < SCENE.iters; i++) {
+    z = v3(clamp(z.x, -l, l) * 2 - z.x, clamp(z.y, -l, l) * 2 - z.y,
+           clamp(z.z, -l, l) * 2 - z.z);
+    FPN r2 = v3_dot(z, z);
+    FPN k = r2 < min_r2 ? FONE / min_r2 : (r2 < FONE ? FONE / r2 : FONE);
+    z = v3_add(v3_scale(z, k * SCENE.box_scale), pos);
+    dr = dr * k * _abs(SCENE.box_scale) + FONE;
+    *trap = _min(*trap, _trap_proximity(z, SCENE.trap));
+  }
+
+  return v3_len(z) / dr;
+}
+
+FPN _scene_de(Vec3_t p, SceneParam_t SCENE, FPN *trap)
+// distance estimate to the selected shape, with the orbit trap of p
+{
+  *trap = 1000 * FONE;
+  return SCENE.shape == 1 ? _mandelbox_de(p, SCENE, trap)
+                          : _mandelbulb_de(p, SCENE, trap);
+}
+
+Vec3_t _scene_normal(Vec3_t p, SceneParam_t SCENE, FPN h) {
+  FPN trap;
+  Vec3_t dx = v3(h, FZERO, FZERO);
+  Vec3_t dy = v3(FZERO, h, FZERO);
+  Vec3_t dz = v3(FZERO, FZERO, h);
+  return v3_norm(v3(_scene_de(v3_add(p, dx), SCENE, &trap) -
+                        _scene_de(v3_sub(p, dx), SCENE, &trap),
+                    _scene_de(v3_add(p, dy), SCENE, &trap) -
+                        _scene_de(v3_sub(p, dy), SCENE, &trap),
+                    _scene_de(v3_add(p, dz), SCENE, &trap) -
+                        _scene_de(v3_sub(p, dz), SCENE, &trap)));
+}
+
+FPN _soft_shadow(Vec3_t p, SceneParam_t SCENE, FPN start)
+// light reaching p through the penumbra of nearby misses, 0 when blocked
+{
+  FPN res = FONE;
+  FPN t = start;
+  FPN trap;
+
+  for (int i = 0; i < SCENE.max_steps && t < SCENE.max_dist; i++) {
+    FPN h = _scene_de(v3_add(p, v3_scale(SCENE.light_dir, t)), SCENE, &trap);
+    if (h < SCENE.detail * t) {
+      return FZERO;
+    }
+    res = _min(res, SCENE.softness * h / t);
+    t += h;
+  }
+
+  return res;
+}
+
+FPN _ambient_occlusion(Vec3_t p, Vec3_t n, SceneParam_t SCENE)
+// darkens creases, from how much closer the surface is than samples along the normal
+{
+  FPN occ = FZERO;
+  FPN w = FONE;
+  FPN trap;
+
+  for (int i = 1; i <= 5; i++) {
+    FPN h = 0.01 + 0.03 * i;
+    occ += w * (h - _scene_de(v3_add(p, v3_scale(n, h)), SCENE, &trap));
+    w *= 0.5;
+  }
+
+  return clamp(FONE - 3 * SCENE.ao * occ, FZERO, FONE);
+}
+
+Vec3_t _ray_march(Vec3_t eye, Vec3_t dir, SceneParam_t SCENE)
+// colour seen along a camera ray, gamma encoded in [0, 1]
+{
+  FPN t = FZERO;
+  FPN trap;
+  int hit = 0;
+
+  for (int i = 0; i < SCENE.max_steps && t < SCENE.max_dist; i++) {
+    FPN d = _scene_de(v3_add(eye, v3_scale(dir, t)), SCENE, &trap);
+    if (d < SCENE.detail * t) {
+      hit = 1;
+      break;
+    }
+    t += d;
+  }
+
+  if (!hit) {
+    FPN s = 0.5 + 0.5 * dir.z;
+    return v3(0.02 + 0.08 * s, 0.02 + 0.1 * s, 0.05 + 0.15 * s);
+  }
+
+  Vec3_t p = v3_add(eye, v3_scale(dir, t));
+  FPN eps = SCENE.detail * t;
+  Vec3_t n = _scene_normal(p, SCENE, eps);
+  _scene_de(p, SCENE, &trap);
+  Vec3_t base = v3(0.5 + 0.5 * sin(trap * SCENE.trap_freqs.f1),
+                   0.5 + 0.5 * sin(trap * SCENE.trap_freqs.f2),
+                   0.5 + 0.5 * sin(trap * SCENE.trap_freqs.f3));
+
+  // phong, with the light's diffuse and specular terms shadowed
+  FPN shadow = _soft_shadow(v3_add(p, v3_scale(n, 2 * eps)), SCENE, 2 * eps);
+  FPN ao = _ambient_occlusion(p, n, SCENE);
+  FPN nl = v3_dot(n, SCENE.light_dir);
+  FPN diffuse = fmax(nl, FZERO) * shadow;
+  Vec3_t refl = v3_sub(v3_scale(n, 2 * nl), SCENE.light_dir);
+  FPN spec = SCENE.specular * pow(fmax(-v3_dot(refl, dir), FZERO), SCENE.shininess) * shadow;
+  FPN light = 0.2 * ao + 0.8 * diffuse;
+
+  return v3(pow(clamp(base.x * light + spec, FZERO, FONE), 1 / 2.2),
+            pow(clamp(base.y * light + spec, FZERO, FONE), 1 / 2.2),
+            pow(clamp(base.z * light + spec, FZERO, FONE), 1 / 2.2));
+}
+
+inline uint _hash(uint x)
+// pcg style integer hash
+{
+  uint state = x * 747796405u + 2891336453u;
+  uint word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+  return (word >> 22u) ^ word;
+}
+
+inline FPN _uniform(uint *state)
+// next pseudo random number in [0, 1)
+{
+  *state = _hash(*state);
+  return *state / 4294967296.0;
+}
+
+void _splat(Complex_t z, Box_t view, int N, int M, __global int *hist) {
+  FPN u = (z.re - view.left) / (view.right - view.left) * M;
+  FPN v = (z.im - view.bot) / (view.top - view.bot) * N;
+  if (u >= 0 && u < M && v >= 0 && v < N) {
+    atomic_inc(&hist[((int)v) * M + (int)u]);
+  }
+}
+
+void _buddhabrot(Complex_t z, Complex_t c, Box_t view, int N, int M,
+                 BuddhabrotParam_t BUDDHA, Formula_t FORMULA,
+                 InteriorChecks_t INTERIOR, Bailout_t BAILOUT,
+                 __global int *hist1, __global int *hist2, __global int *hist3)
+// splats the orbit of z into histogram k if it escapes within max_iter[k]
+// iterations, or for anti, its first max_iter[k] points if it does not
+{
+  int maxiter = max(max(BUDDHA.max_iter[0], BUDDHA.max_iter[1]), BUDDHA.max_iter[2]);
+  int period;
+  int n = _escape_iter(z, c, maxiter, FORMULA, _plane_interior(INTERIOR, view, M),
+                       BAILOUT, &period);
+
+  int in1 = BUDDHA.anti ? n >= BUDDHA.max_iter[0] : n < BUDDHA.max_iter[0];
+  int in2 = BUDDHA.anti ? n >= BUDDHA.max_iter[1] : n < BUDDHA.max_iter[1];
+  int in3 = BUDDHA.anti ? n >= BUDDHA.max_iter[2] : n < BUDDHA.max_iter[2];
+  if (!(in1 || in2 || in3)) {
+    return;
+  }
+
+  for (int t = 0; t < n; t++) {
+    z = _iterate(z, c, FORMULA);
+    if (in1 && t < BUDDHA.max_iter[0]) {
+      _splat(z, view, N, M, hist1);
+    }
+    if (in2 && t < BUDDHA.max_iter[1]) {
+      _splat(z, view, N, M, hist2);
+    }
+    if (in3 && t < BUDDHA.max_iter[2]) {
+      _splat(z, view, N, M, hist3);
+    }
+  }
+}
+
+FPN _palette_coord(FPN v, PaletteMapping_t MAPPING)
+// position in [0, 1] on the palette for a field value
+{
+  FPN u = v * MAPPING.scale + MAPPING.offset;
+  if (MAPPING.repeat_mode == 1) {
+    u = u - floor(u);
+  } else if (MAPPING.repeat_mode == 2) {
+    u = u - 2 * floor(u / 2);
+    u = u > FONE ? 2 - u : u;
+  }
+  return u < FZERO ? FZERO : (u > FONE ? FONE : u);
+}
@@ -0,0 +1,266 @@
+//! Gradient palettes for single field colouring. Stops are interpolated on the host into a
+//! lookup table, which the `map_palette` kernel indexes by (scaled, offset and wrapped) field value.
+
+use egui_inspect::egui::{self, pos2, vec2, Color32, DragValue, Rect, Sense, Stroke};
+use egui_inspect::logging::log::error;
+use egui_inspect::EguiInspect;
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+
+use crate::color::{linear_to_oklab, linear_to_srgb3, oklab_to_linear, srgb_to_linear3};
+use crate::gradient_import::load_gradient;
+use crate::wrapper_types::PaletteMapping;
+
+/// Entries in the lookup table given to the colouring kernel
+pub static PALETTE_LUT_LEN: usize = 1024;
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ColorStop {
+    /// position along the gradient, in [0, 1]
+    pub pos: f64,
+    pub color: [u8; 3],
+}
+
+/// Colour space stops are blended in
+#[derive(Default, EguiInspect, PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Interpolation {
+    Rgb,
+    LinearRgb,
+    #[default]
+    OkLab,
+}
+
+/// Handling of field values mapped outside of [0, 1]
+#[derive(Default, EguiInspect, PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum RepeatMode {
+    #[default]
+    Clamp,
+    Repeat,
+    Mirror,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Palette {
+    pub stops: Vec<ColorStop>,
+    pub interpolation: Interpolation,
+    pub repeat: RepeatMode,
+    /// field value to palette position scale, values beyond 1 wrap when repeating
+    pub scale: f64,
+    pub offset: f64,
+    #[serde(skip)]
+    selected: Option<usize>,
+}
+
+impl PartialEq for Palette {
+    fn eq(&self, other: &Self) -> bool {
+        self.stops == other.stops
+            && self.interpolation == other.interpolation
+            && self.repeat == other.repeat
+            && self.scale == other.scale
+            && self.offset == other.offset
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        let stop = |pos, color| ColorStop { pos, color };
+        Self {
+            stops: vec![
+                stop(0.0, [0, 7, 100]),
+                stop(0.16, [32, 107, 203]),
+                stop(0.42, [237, 255, 255]),
+                stop(0.64, [255, 170, 0]),
+                stop(0.86, [0, 2, 0]),
+                stop(1.0, [0, 0, 0]),
+            ],
+            interpolation: Default::default(),
+            repeat: Default::default(),
+            scale: 1.0,
+            offset: 0.0,
+            selected: None,
+        }
+    }
+}
+
+impl Palette {
+    pub fn from_stops(stops: Vec<ColorStop>, interpolation: Interpolation) -> Self {
+        Self {
+            stops,
+            interpolation,
+            ..Default::default()
+        }
+    }
+
+    fn sorted_stops(&self) -> Vec<ColorStop> {
+        let mut stops = self.stops.clone();
+        stops.sort_by(|a, b| a.pos.total_cmp(&b.pos));
+        stops
+    }
+
+    fn blend(&self, a: [u8; 3], b: [u8; 3], t: f32) -> [u8; 3] {
+        let lerp = |a: [f32; 3], b: [f32; 3]| [0, 1, 2].map(|k| a[k] + t * (b[k] - a[k]));
+        match self.interpolation {
+            Interpolation::Rgb => {
+                lerp(a.map(f32::from), b.map(f32::from)).map(|v| v.round().clamp(0.0, 255.0) as u8)
+            }
+            Interpolation::LinearRgb => {
+                linear_to_srgb3(lerp(srgb_to_linear3(a), srgb_to_linear3(b)))
+            }
+            Interpolation::OkLab => linear_to_srgb3(oklab_to_linear(lerp(
+                linear_to_oklab(srgb_to_linear3(a)),
+                linear_to_oklab(srgb_to_linear3(b)),
+            ))),
+        }
+    }
+
+    /// Colour at position `u` in [0, 1]
+    pub fn sample(&self, u: f64) -> [u8; 3] {
+        let stops = self.sorted_stops();
+        let (first, last) = match (stops.first(), stops.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return [0, 0, 0],
+        };
+        if u <= first.pos {
+            return first.color;
+        }
+        if u >= last.pos {
+            return last.color;
+        }
+        let k = stops.partition_point(|s| s.pos <= u);
+        let (a, b) = (stops[k - 1], stops[k]);
+        let t = (u - a.pos) / (b.pos - a.pos).max(f64::EPSILON);
+        self.blend(a.color, b.color, t as f32)
+    }
+
+    /// Lookup table of `PALETTE_LUT_LEN` rgb entries evenly spread over [0, 1]
+    pub fn lut(&self) -> Array2<u8> {
+        let mut lut = Array2::zeros((PALETTE_LUT_LEN, 3));
+        for (k, mut row) in lut.outer_iter_mut().enumerate() {
+            let color = self.sample(k as f64 / (PALETTE_LUT_LEN - 1) as f64);
+            row.as_slice_mut().unwrap().copy_from_slice(&color);
+        }
+        lut
+    }
+
+    pub fn mapping(&self) -> PaletteMapping {
+        PaletteMapping {
+            scale: self.scale,
+            offset: self.offset,
+            repeat_mode: match self.repeat {
+                RepeatMode::Clamp => 0,
+                RepeatMode::Repeat => 1,
+                RepeatMode::Mirror => 2,
+            },
+        }
+    }
+
+    /// Gradient strip with a draggable marker per stop, clicking the strip adds a stop
+    fn gradient_editor(&mut self, ui: &mut egui::Ui) {
+        let width = ui.available_width().min(300.0);
+        let (rect, strip) = ui.allocate_exact_size(vec2(width, 24.0), Sense::click());
+        let painter = ui.painter_at(rect);
+
+        let n_strips = (width as usize).max(1);
+        let lut = self.lut();
+        for k in 0..n_strips {
+            let u = k as f32 / n_strips as f32;
+            let c = lut.row((u * (PALETTE_LUT_LEN - 1) as f32) as usize);
+            let x0 = rect.left() + u * width;
+            painter.rect_filled(
+                Rect::from_min_max(pos2(x0, rect.top()), pos2(x0 + 1.0, rect.bottom())),
+                0.0,
+                Color32::from_rgb(c[0], c[1], c[2]),
+            );
+        }
+
+        if strip.clicked() {
+            if let Some(at) = strip.interact_pointer_pos() {
+                let pos = ((at.x - rect.left()) / width).clamp(0.0, 1.0) as f64;
+                self.stops.push(ColorStop {
+                    pos,
+                    color: self.sample(pos),
+                });
+                self.selected = Some(self.stops.len() - 1);
+            }
+        }
+
+        let (_, markers) = ui.allocate_exact_size(vec2(width, 12.0), Sense::hover());
+        for (k, stop) in self.stops.iter_mut().enumerate() {
+            let x = rect.left() + stop.pos as f32 * width;
+            let marker = Rect::from_center_size(pos2(x, markers.rect.center().y), vec2(8.0, 12.0));
+            let response = ui.interact(marker, strip.id.with(k), Sense::click_and_drag());
+            if response.dragged() {
+                stop.pos = (stop.pos + (response.drag_delta().x / width) as f64).clamp(0.0, 1.0);
+            }
+            if response.clicked() || response.drag_started() {
+                self.selected = Some(k);
+            }
+            let outline = if self.selected == Some(k) {
+                Stroke::new(2.0, ui.visuals().strong_text_color())
+            } else {
+                Stroke::new(1.0, ui.visuals().weak_text_color())
+            };
+            let [r, g, b] = stop.color;
+            ui.painter()
+                .rect(marker, 1.0, Color32::from_rgb(r, g, b), outline);
+        }
+    }
+}
+
+impl EguiInspect for Palette {
+    fn inspect(&self, _label: &str, _ui: &mut egui::Ui) {
+        todo!()
+    }
+
+    fn inspect_mut(&mut self, label: &str, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(label);
+            if ui.button("Import gradient").clicked() {
+                if let Some(fpath) = rfd::FileDialog::new()
+                    .set_directory(".")
+                    .add_filter("Gradient", &["ggr", "map", "cpt"])
+                    .pick_file()
+                {
+                    // keeps the current mapping onto the field
+                    match load_gradient(fpath) {
+                        Ok(imported) => {
+                            self.stops = imported.stops;
+                            self.interpolation = imported.interpolation;
+                            self.selected = None;
+                        }
+                        Err(err) => error!("{err}"),
+                    }
+                }
+            }
+        });
+        self.gradient_editor(ui);
+
+        let selected = self.selected.filter(|&k| k < self.stops.len());
+        if let Some(k) = selected {
+            let can_remove = self.stops.len() > 2;
+            let stop = &mut self.stops[k];
+            let mut remove = false;
+            ui.horizontal(|ui| {
+                ui.color_edit_button_srgb(&mut stop.color);
+                ui.add(DragValue::new(&mut stop.pos).speed(0.005).range(0.0..=1.0));
+                remove = can_remove && ui.button("Remove stop").clicked();
+            });
+            if remove {
+                self.stops.remove(k);
+                self.selected = None;
+            }
+        } else {
+            ui.label("Click the gradient to add a stop, click a marker to edit it");
+        }
+
+        self.interpolation.inspect_mut("interpolation", ui);
+        self.repeat.inspect_mut("repeat", ui);
+        ui.add(
+            egui::Slider::new(&mut self.scale, 0.01..=100.0)
+                .logarithmic(true)
+                .text("scale"),
+        );
+        ui.add(egui::Slider::new(&mut self.offset, -1.0..=1.0).text("offset"));
+    }
+}
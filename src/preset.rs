@@ -0,0 +1,91 @@
+//! Everything needed to reproduce a render, saved as RON either on its own or inside png exports
+
+use image::ColorType;
+use ndarray::Array3;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::image_io::{create_png_writer, read_png_text, save_png_with_text, PngWriter};
+use crate::FractalParams;
+
+/// png text chunk keyword under which exported images carry their preset
+static PNG_PRESET_KEYWORD: &str = "fractal-preset";
+
+#[derive(Serialize, Deserialize)]
+pub struct Preset {
+    pub params: FractalParams,
+    /// custom iteration function source, see `FunctionEditor`
+    pub custom_code: String,
+    pub size: (usize, usize),
+}
+
+impl Preset {
+    pub fn to_ron(&self) -> Result<String, String> {
+        ron::ser::to_string_pretty(self, Default::default()).map_err(|err| err.to_string())
+    }
+
+    pub fn from_ron(text: &str) -> Result<Self, String> {
+        ron::from_str(text).map_err(|err| err.to_string())
+    }
+
+    pub fn save(&self, fpath: impl AsRef<Path>) -> Result<(), String> {
+        std::fs::write(fpath, self.to_ron()?).map_err(|err| err.to_string())
+    }
+
+    pub fn load(fpath: impl AsRef<Path>) -> Result<Self, String> {
+        let text = std::fs::read_to_string(fpath).map_err(|err| err.to_string())?;
+        Self::from_ron(&text)
+    }
+
+    /// Where `save_image` writes to for `fpath`, which gets a png extension when it has none
+    pub fn image_path(fpath: impl AsRef<Path>) -> PathBuf {
+        let fpath = fpath.as_ref();
+        match fpath.extension() {
+            Some(_) => fpath.to_path_buf(),
+            None => fpath.with_extension("png"),
+        }
+    }
+
+    pub fn is_png(fpath: impl AsRef<Path>) -> bool {
+        Self::image_path(fpath)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("png"))
+    }
+
+    /// Saves a render of this preset, pngs (the default without an extension) embed the preset,
+    /// other formats are plain images
+    pub fn save_image(&self, fpath: impl AsRef<Path>, rgb: &Array3<u8>) -> Result<(), String> {
+        let fpath = Self::image_path(fpath);
+        if Self::is_png(&fpath) {
+            self.save_png(fpath, rgb)
+        } else {
+            let (h, w, _) = rgb.dim();
+            image::save_buffer(
+                fpath,
+                rgb.as_slice().unwrap(),
+                w as u32,
+                h as u32,
+                ColorType::Rgb8,
+            )
+            .map_err(|err| err.to_string())
+        }
+    }
+
+    /// Saves the rendered image as a png with this preset embedded
+    pub fn save_png(&self, fpath: impl AsRef<Path>, rgb: &Array3<u8>) -> Result<(), String> {
+        save_png_with_text(fpath, rgb, &[(PNG_PRESET_KEYWORD, self.to_ron()?)])
+    }
+
+    /// As `save_png`, for image data to be streamed in afterwards
+    pub fn create_png_writer(&self, fpath: impl AsRef<Path>) -> Result<PngWriter, String> {
+        create_png_writer(fpath, self.size, &[(PNG_PRESET_KEYWORD, self.to_ron()?)])
+    }
+
+    /// Restores the preset embedded by `save_png`
+    pub fn load_png(fpath: impl AsRef<Path>) -> Result<Self, String> {
+        match read_png_text(fpath, PNG_PRESET_KEYWORD)? {
+            Some(text) => Self::from_ron(&text),
+            None => Err("image does not carry render parameters".to_string()),
+        }
+    }
+}
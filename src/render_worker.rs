@@ -0,0 +1,322 @@
+//! Long lived render thread owning a backend. The UI sends commands over a channel and polls
+//! the replies, renders superseded by a newer request are skipped, or stopped between passes
+//! when already running. Progressive renders first reply with coarse images from smaller
+//! backends, then refine them. The smaller backends are created on the first progressive
+//! render, so the OpenCL program is built once more per level at that point (and again after
+//! each recompile).
+
+use egui_inspect::logging::log::error;
+use ndarray::Array3;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use crate::backend::{create_backend, BackendKind, RenderBackend};
+use crate::preset::Preset;
+use crate::{render_progressive, FractalParams, FractalVisualisationType};
+
+/// Downscaling of the coarse passes of progressive renders, coarsest first
+const COARSE_FACTORS: [usize; 2] = [8, 2];
+
+enum Command {
+    Render {
+        generation: u64,
+        params: Box<FractalParams>,
+        progressive: bool,
+    },
+    /// replaces the backend at the same size, for new custom code or another kind
+    Recompile {
+        backend_kind: BackendKind,
+        code: String,
+    },
+    /// reallocates the image buffers, keeping the program
+    Resize { size: (usize, usize) },
+    /// saves the last completed render along with `preset`, whose params are replaced by those
+    /// the render was made with
+    Save { fpath: PathBuf, preset: Box<Preset> },
+}
+
+pub enum Reply {
+    Rendered {
+        rgb: Array3<u8>,
+        /// buddhabrot samples and passes accumulated so far
+        histogram_samples: usize,
+        histogram_passes: usize,
+        /// a finer pass of the same render follows
+        refining: bool,
+    },
+    /// a newer render was requested before this one finished
+    Superseded,
+    Failed(String),
+    Recompiled(Result<(), String>),
+    /// later renders are of `size`
+    Resized {
+        size: (usize, usize),
+    },
+    Saved(Result<(), String>),
+}
+
+pub struct RenderWorker {
+    commands: Sender<Command>,
+    replies: Receiver<Reply>,
+    /// generation of the latest render request
+    latest: Arc<AtomicU64>,
+    /// render requests yet to be replied to
+    in_flight: usize,
+    _thread: JoinHandle<()>,
+}
+
+impl RenderWorker {
+    /// `backend_kind` is that of `backend`, for the coarse backend of progressive renders
+    pub fn new(backend_kind: BackendKind, backend: Box<dyn RenderBackend>) -> Self {
+        let (commands, command_rx) = channel();
+        let (reply_tx, replies) = channel();
+        let latest = Arc::new(AtomicU64::new(0));
+        let thread_latest = latest.clone();
+        let _thread = std::thread::spawn(move || {
+            work(backend_kind, backend, command_rx, reply_tx, thread_latest);
+        });
+        Self {
+            commands,
+            replies,
+            latest,
+            in_flight: 0,
+            _thread,
+        }
+    }
+
+    fn send(&self, command: Command) {
+        if self.commands.send(command).is_err() {
+            error!("render worker has stopped");
+        }
+    }
+
+    /// Queues a render, superseding any that are queued or running. When `progressive`, coarse
+    /// images and an unsupersampled one are replied first, buddhabrots are already shown pass
+    /// by pass.
+    pub fn render(&mut self, params: FractalParams, progressive: bool) {
+        let generation = self.latest.fetch_add(1, Ordering::Relaxed) + 1;
+        self.in_flight += 1;
+        self.send(Command::Render {
+            generation,
+            params: Box::new(params),
+            progressive,
+        });
+    }
+
+    pub fn recompile(&self, backend_kind: BackendKind, code: String) {
+        self.send(Command::Recompile { backend_kind, code });
+    }
+
+    pub fn resize(&self, size: (usize, usize)) {
+        self.send(Command::Resize { size });
+    }
+
+    pub fn save(&self, fpath: PathBuf, preset: Preset) {
+        self.send(Command::Save {
+            fpath,
+            preset: Box::new(preset),
+        });
+    }
+
+    /// Whether renders are queued or running
+    pub fn is_busy(&self) -> bool {
+        self.in_flight > 0
+    }
+
+    /// Replies received since the last poll, in order
+    pub fn poll(&mut self) -> Vec<Reply> {
+        let replies: Vec<Reply> = self.replies.try_iter().collect();
+        for reply in &replies {
+            if matches!(
+                reply,
+                Reply::Rendered {
+                    refining: false,
+                    ..
+                } | Reply::Superseded
+                    | Reply::Failed(_)
+            ) {
+                self.in_flight -= 1;
+            }
+        }
+        replies
+    }
+}
+
+fn coarse_size(size: (usize, usize), factor: usize) -> (usize, usize) {
+    (size.0.div_ceil(factor), size.1.div_ceil(factor))
+}
+
+/// Backends of the same kind at each of `COARSE_FACTORS` times less resolution, with their
+/// factor, skipping those that are unavailable
+fn coarse_backends(
+    backend_kind: BackendKind,
+    size: (usize, usize),
+    code: Option<String>,
+) -> Vec<(usize, Box<dyn RenderBackend>)> {
+    COARSE_FACTORS
+        .into_iter()
+        .filter_map(|factor| {
+            match create_backend(backend_kind, coarse_size(size, factor), code.clone()) {
+                Ok(coarse) => Some((factor, coarse)),
+                Err(err) => {
+                    error!("Could not create 1/{factor} resolution backend: {err}");
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Nearest neighbour upscaling of `rgb` to `dims`
+fn upscale(rgb: &Array3<u8>, (h, w): (usize, usize)) -> Array3<u8> {
+    let (ch, cw, _) = rgb.dim();
+    Array3::from_shape_fn((h, w, 3), |(i, j, k)| rgb[[i * ch / h, j * cw / w, k]])
+}
+
+fn rendered(backend: &dyn RenderBackend, rgb: Array3<u8>, refining: bool) -> Reply {
+    let state = backend.histogram_state();
+    Reply::Rendered {
+        rgb,
+        histogram_samples: state.samples,
+        histogram_passes: state.passes,
+        refining,
+    }
+}
+
+/// Unsupersampled pass of a progressive render, replied as refining. Returns the final reply
+/// when the render cannot go on.
+fn coarse_pass(
+    pass_backend: &mut dyn RenderBackend,
+    params: &FractalParams,
+    dims: (usize, usize),
+    cancelled: &dyn Fn() -> bool,
+    replies: &Sender<Reply>,
+) -> Option<Reply> {
+    let pass_params = FractalParams {
+        supersampling: 1,
+        ..params.clone()
+    };
+    if let Err(err) = render_progressive(pass_backend, pass_params, cancelled) {
+        return Some(Reply::Failed(err));
+    }
+    if cancelled() {
+        return Some(Reply::Superseded);
+    }
+    let rgb = upscale(pass_backend.rgb(), dims);
+    let _ = replies.send(rendered(pass_backend, rgb, true));
+    None
+}
+
+/// Renders `params`, replying with the coarser passes along the way, and returns the final reply
+fn render(
+    backend: &mut dyn RenderBackend,
+    coarse: &mut [(usize, Box<dyn RenderBackend>)],
+    params: FractalParams,
+    progressive: bool,
+    cancelled: &dyn Fn() -> bool,
+    replies: &Sender<Reply>,
+) -> Reply {
+    let progressive =
+        progressive && !matches!(params.vis_type, FractalVisualisationType::Buddhabrot { .. });
+    if progressive {
+        let (h, w, _) = backend.rgb().dim();
+        for (_, coarse) in coarse {
+            if let Some(reply) = coarse_pass(coarse.as_mut(), &params, (h, w), cancelled, replies) {
+                return reply;
+            }
+        }
+        if params.supersampling > 1 {
+            if let Some(reply) = coarse_pass(backend, &params, (h, w), cancelled, replies) {
+                return reply;
+            }
+        }
+    }
+    match render_progressive(backend, params, cancelled) {
+        Ok(_) if cancelled() => Reply::Superseded,
+        Ok(_) => rendered(backend, backend.rgb().clone(), false),
+        Err(err) => Reply::Failed(err),
+    }
+}
+
+/// Runs until the `RenderWorker` is dropped
+fn work(
+    backend_kind: BackendKind,
+    mut backend: Box<dyn RenderBackend>,
+    commands: Receiver<Command>,
+    replies: Sender<Reply>,
+    latest: Arc<AtomicU64>,
+) {
+    // created lazily, so progressive renders are only paid for when used
+    let mut coarse = vec![];
+    let mut coarse_created = false;
+    let mut coarse_inputs = (backend_kind, None);
+    // cancelled renders leave partial images in the backend, so saves use this instead
+    let mut last_render: Option<(FractalParams, Array3<u8>)> = None;
+    for command in commands {
+        let reply = match command {
+            Command::Render {
+                generation,
+                params,
+                progressive,
+            } => {
+                let cancelled = || latest.load(Ordering::Relaxed) != generation;
+                if cancelled() {
+                    Reply::Superseded
+                } else {
+                    if progressive && !coarse_created {
+                        let (h, w, _) = backend.rgb().dim();
+                        let (kind, code) = coarse_inputs.clone();
+                        coarse = coarse_backends(kind, (h, w), code);
+                        coarse_created = true;
+                    }
+                    let reply = render(
+                        backend.as_mut(),
+                        &mut coarse,
+                        (*params).clone(),
+                        progressive,
+                        &cancelled,
+                        &replies,
+                    );
+                    if let Reply::Rendered { rgb, .. } = &reply {
+                        last_render = Some((*params, rgb.clone()));
+                    }
+                    reply
+                }
+            }
+            Command::Recompile { backend_kind, code } => {
+                let (h, w, _) = backend.rgb().dim();
+                Reply::Recompiled(
+                    create_backend(backend_kind, (h, w), Some(code.clone())).map(|new_backend| {
+                        backend = new_backend;
+                        last_render = None;
+                        coarse.clear();
+                        coarse_created = false;
+                        coarse_inputs = (backend_kind, Some(code));
+                    }),
+                )
+            }
+            Command::Resize { size } => {
+                backend.resize(size);
+                last_render = None;
+                for (factor, coarse) in coarse.iter_mut() {
+                    coarse.resize(coarse_size(size, *factor));
+                }
+                Reply::Resized { size }
+            }
+            Command::Save { fpath, preset } => Reply::Saved(match &last_render {
+                Some((params, rgb)) => Preset {
+                    params: params.clone(),
+                    ..*preset
+                }
+                .save_image(fpath, rgb),
+                None => Err("nothing to save, no render has completed yet".to_string()),
+            }),
+        };
+        if replies.send(reply).is_err() {
+            break;
+        }
+    }
+}
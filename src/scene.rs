@@ -0,0 +1,231 @@
+//! Ray marched 3D fractals, the Mandelbulb and Mandelbox distance estimates are marched from an
+//! orbiting camera and shaded straight into the rgb image. The film spans the width of the 2D
+//! view, so sub-pixel shifts and tiles of the view carry over to the camera.
+
+use egui_inspect::{EguiInspect, InspectNumber};
+use serde::{Deserialize, Serialize};
+
+use crate::frame_view::Navigation;
+use crate::wrapper_types::{BBox, Freqs, ProxType, SceneParam, Vec3};
+
+#[derive(EguiInspect, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct BulbParams {
+    #[inspect(min = 2.0, max = 16.0)]
+    power: f64,
+}
+
+impl Default for BulbParams {
+    fn default() -> Self {
+        Self { power: 8.0 }
+    }
+}
+
+#[derive(EguiInspect, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct BoxParams {
+    #[inspect(min = -3.0, max = 3.0)]
+    scale: f64,
+    /// radius under which the sphere fold is a constant scaling
+    #[inspect(min = 0.05, max = 1.0)]
+    min_radius: f64,
+    #[inspect(min = 0.5, max = 2.0)]
+    fold_limit: f64,
+}
+
+impl Default for BoxParams {
+    fn default() -> Self {
+        Self {
+            scale: -1.5,
+            min_radius: 0.5,
+            fold_limit: 1.0,
+        }
+    }
+}
+
+#[derive(EguiInspect, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum Shape {
+    Mandelbulb { bulb: BulbParams },
+    Mandelbox { mandelbox: BoxParams },
+}
+
+impl Default for Shape {
+    fn default() -> Self {
+        Shape::Mandelbulb {
+            bulb: Default::default(),
+        }
+    }
+}
+
+/// Orbits `target`, z is up
+#[derive(EguiInspect, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Camera {
+    pub target: Vec3,
+    /// degrees around z
+    #[inspect(min = -180.0, max = 180.0)]
+    pub yaw: f64,
+    /// degrees above the xy plane
+    #[inspect(min = -89.0, max = 89.0)]
+    pub pitch: f64,
+    #[inspect(log_slider, min = 0.01, max = 20.0)]
+    pub distance: f64,
+    /// horizontal field of view in degrees
+    #[inspect(min = 10.0, max = 120.0)]
+    pub fov: f64,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            target: Default::default(),
+            yaw: 30.0,
+            pitch: 25.0,
+            distance: 3.0,
+            fov: 50.0,
+        }
+    }
+}
+
+#[derive(EguiInspect, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Lighting {
+    /// degrees around z
+    #[inspect(min = -180.0, max = 180.0)]
+    pub light_yaw: f64,
+    /// degrees above the xy plane
+    #[inspect(min = -89.0, max = 89.0)]
+    pub light_pitch: f64,
+    /// larger is harder shadows
+    #[inspect(log_slider, min = 1.0, max = 128.0)]
+    pub softness: f64,
+    #[inspect(min = 0.0, max = 4.0)]
+    pub ambient_occlusion: f64,
+    #[inspect(min = 0.0, max = 1.0)]
+    pub specular: f64,
+    #[inspect(log_slider, min = 1.0, max = 256.0)]
+    pub shininess: f64,
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Self {
+            light_yaw: 60.0,
+            light_pitch: 50.0,
+            softness: 16.0,
+            ambient_occlusion: 1.0,
+            specular: 0.4,
+            shininess: 32.0,
+        }
+    }
+}
+
+#[derive(EguiInspect, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarchSettings {
+    /// iterations of the distance estimate
+    #[inspect(min = 1.0, max = 50.0)]
+    pub iters: i32,
+    #[inspect(log_slider, min = 16.0, max = 2048.0)]
+    pub max_steps: i32,
+    /// hit distance per unit of distance travelled
+    #[inspect(log_slider, min = 1e-5, max = 1e-1)]
+    pub detail: f64,
+    #[inspect(log_slider, min = 1.0, max = 100.0)]
+    pub max_dist: f64,
+}
+
+impl Default for MarchSettings {
+    fn default() -> Self {
+        Self {
+            iters: 10,
+            max_steps: 256,
+            detail: 1e-3,
+            max_dist: 20.0,
+        }
+    }
+}
+
+/// UI for the ray marched scene, dragging the view orbits the camera and scrolling dollies it
+#[derive(Default, EguiInspect, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneParamUI {
+    pub shape: Shape,
+    pub camera: Camera,
+    pub lighting: Lighting,
+    pub march: MarchSettings,
+    /// orbit trap colouring, as for the 2D min prox field
+    pub trap: ProxType,
+    pub trap_freqs: Freqs,
+}
+
+/// Unit vector at yaw degrees around z and pitch degrees above the xy plane
+fn direction(yaw: f64, pitch: f64) -> Vec3 {
+    let (yaw, pitch) = (yaw.to_radians(), pitch.to_radians());
+    Vec3::new(
+        pitch.cos() * yaw.cos(),
+        pitch.cos() * yaw.sin(),
+        pitch.sin(),
+    )
+}
+
+impl SceneParamUI {
+    /// `frame` is the full (unshifted, untiled) view the film spans
+    pub fn get_c_struct(&self, frame: BBox) -> SceneParam {
+        let Camera {
+            target,
+            yaw,
+            pitch,
+            distance,
+            fov,
+        } = self.camera;
+        let eye = target + direction(yaw, pitch) * distance;
+        let forward = (target - eye).normalized();
+        let right = forward.cross(Vec3::new(0.0, 0.0, 1.0)).normalized();
+        let up = right.cross(forward);
+
+        let (shape, power, box_params) = match self.shape {
+            Shape::Mandelbulb { bulb } => (0, bulb.power, BoxParams::default()),
+            Shape::Mandelbox { mandelbox } => (1, 8.0, mandelbox),
+        };
+
+        SceneParam {
+            frame,
+            eye,
+            forward,
+            right,
+            up,
+            fov_scale: (fov.to_radians() / 2.0).tan(),
+            shape,
+            power,
+            box_scale: box_params.scale,
+            min_radius: box_params.min_radius,
+            fold_limit: box_params.fold_limit,
+            iters: self.march.iters,
+            max_steps: self.march.max_steps,
+            detail: self.march.detail,
+            max_dist: self.march.max_dist,
+            light_dir: direction(self.lighting.light_yaw, self.lighting.light_pitch),
+            softness: self.lighting.softness,
+            ao: self.lighting.ambient_occlusion,
+            specular: self.lighting.specular,
+            shininess: self.lighting.shininess,
+            trap: self.trap,
+            trap_freqs: self.trap_freqs,
+        }
+    }
+
+    /// Drags orbit the camera and zooms dolly it, selections and recentering are ignored
+    pub fn navigate(&mut self, nav: Navigation) {
+        let camera = &mut self.camera;
+        match nav {
+            Navigation::Pan(delta) => {
+                camera.yaw -= delta.x as f64 * 180.0;
+                camera.yaw = (camera.yaw + 540.0).rem_euclid(360.0) - 180.0;
+                camera.pitch = (camera.pitch + delta.y as f64 * 180.0).clamp(-89.0, 89.0);
+            }
+            Navigation::ZoomAbout { factor, .. } => {
+                camera.distance = (camera.distance / factor).clamp(0.01, 20.0);
+            }
+            Navigation::ZoomToRect(_) | Navigation::Recenter(_) => {}
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! General orbit traps, the orbit is measured against a union of shapes, each defined in its
+//! own frame (unit circle, segment from -1 to 1, ...) and placed by an offset, rotation and
+//! scale. Fields give either the distance or where in its shape's frame the orbit was trapped.
+
+use egui_inspect::egui::{self, Button};
+use egui_inspect::{EguiInspect, InspectNumber};
+use serde::{Deserialize, Serialize};
+
+use crate::backend::BackendResult;
+use crate::wrapper_types::{Complex, ShapeTrapParam, TrapShape, MAX_TRAP_SHAPES};
+
+#[derive(EguiInspect, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct PolygonParams {
+    #[inspect(min = 3.0, max = 16.0)]
+    sides: i32,
+}
+
+impl Default for PolygonParams {
+    fn default() -> Self {
+        Self { sides: 5 }
+    }
+}
+
+/// Shape in its own frame
+#[derive(Default, EguiInspect, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum TrapShapeKind {
+    #[default]
+    Point,
+    /// unit circle
+    Circle,
+    /// from -1 to 1 along re
+    Segment,
+    /// segments from -1 to 1 along re and im
+    Cross,
+    /// regular, with circumradius 1 and a vertex at 1
+    Polygon { polygon: PolygonParams },
+    /// `trap_sdf` from the function editor, OpenCL only (a point on the CPU)
+    Custom,
+}
+
+#[derive(EguiInspect, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrapShapeUI {
+    pub kind: TrapShapeKind,
+    pub offset: Complex,
+    /// degrees
+    #[inspect(min = -180.0, max = 180.0)]
+    pub angle: f64,
+    #[inspect(log_slider, min = 0.01, max = 10.0)]
+    pub scale: f64,
+}
+
+impl Default for TrapShapeUI {
+    fn default() -> Self {
+        Self {
+            kind: TrapShapeKind::Circle,
+            offset: Complex { re: 0.0, im: 0.0 },
+            angle: 0.0,
+            scale: 1.0,
+        }
+    }
+}
+
+impl TrapShapeUI {
+    fn get_c_struct(&self) -> TrapShape {
+        let (kind, sides) = match self.kind {
+            TrapShapeKind::Point => (0, 0),
+            TrapShapeKind::Circle => (1, 0),
+            TrapShapeKind::Segment => (2, 0),
+            TrapShapeKind::Cross => (3, 0),
+            TrapShapeKind::Polygon { polygon } => (4, polygon.sides),
+            TrapShapeKind::Custom => (5, 0),
+        };
+        TrapShape {
+            kind,
+            sides,
+            offset: self.offset,
+            angle: self.angle.to_radians(),
+            scale: self.scale,
+        }
+    }
+}
+
+/// How the distances along an orbit combine
+#[derive(Default, EguiInspect, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum TrapMode {
+    #[default]
+    MinDistance,
+    AverageDistance,
+    /// first orbit point within the threshold, its distance as a fraction of the threshold
+    /// (1 when nothing was hit)
+    FirstHit,
+}
+
+/// Field read out of a shape trap
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TrapOutput {
+    Distance,
+    /// re of the trapping point in its shape's frame, as uv over the [-1, 1] square
+    HitRe,
+    HitIm,
+}
+
+/// UI for a union of trap shapes
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShapeTrapUI {
+    pub shapes: Vec<TrapShapeUI>,
+    pub mode: TrapMode,
+    pub threshold: f64,
+}
+
+impl Default for ShapeTrapUI {
+    fn default() -> Self {
+        Self {
+            shapes: vec![Default::default()],
+            mode: Default::default(),
+            threshold: 0.05,
+        }
+    }
+}
+
+impl ShapeTrapUI {
+    pub fn get_c_struct(&self) -> BackendResult<ShapeTrapParam> {
+        if self.shapes.is_empty() {
+            return Err("shape trap has no shapes".to_string());
+        }
+        if self.shapes.len() > MAX_TRAP_SHAPES {
+            return Err(format!("shape trap has more than {MAX_TRAP_SHAPES} shapes"));
+        }
+        let mut shapes = [TrapShape::default(); MAX_TRAP_SHAPES];
+        for (shape, ui) in shapes.iter_mut().zip(&self.shapes) {
+            *shape = ui.get_c_struct();
+        }
+        Ok(ShapeTrapParam {
+            shapes,
+            n_shapes: self.shapes.len() as i32,
+            mode: self.mode as i32,
+            threshold: self.threshold,
+        })
+    }
+}
+
+impl EguiInspect for ShapeTrapUI {
+    fn inspect(&self, _label: &str, _ui: &mut egui::Ui) {
+        todo!()
+    }
+
+    fn inspect_mut(&mut self, label: &str, ui: &mut egui::Ui) {
+        ui.label(label);
+        let n_shapes = self.shapes.len();
+        let mut removed = None;
+        for (k, shape) in self.shapes.iter_mut().enumerate() {
+            ui.push_id(k, |ui| {
+                ui.group(|ui| {
+                    shape.inspect_mut(&format!("shape {}", k + 1), ui);
+                    if ui
+                        .add_enabled(n_shapes > 1, Button::new("Remove"))
+                        .clicked()
+                    {
+                        removed = Some(k);
+                    }
+                });
+            });
+        }
+        if let Some(k) = removed {
+            self.shapes.remove(k);
+        }
+        if ui
+            .add_enabled(
+                self.shapes.len() < MAX_TRAP_SHAPES,
+                Button::new("Add shape"),
+            )
+            .clicked()
+        {
+            self.shapes.push(Default::default());
+        }
+        self.mode.inspect_mut("mode", ui);
+        if self.mode == TrapMode::FirstHit {
+            ui.add(
+                egui::Slider::new(&mut self.threshold, 1e-4..=1.0)
+                    .logarithmic(true)
+                    .text("threshold"),
+            );
+        }
+    }
+}
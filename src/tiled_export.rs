@@ -0,0 +1,157 @@
+//! Exports larger than a single backend allocation. The view is split into tiles rendered one
+//! after another by the same kernels, each band of tiles is stitched on the host and streamed
+//! into the png, so only one band is ever held in memory.
+
+use egui_inspect::egui::{self, DragValue};
+use egui_inspect::logging::log::{error, info};
+use ndarray::{s, Array3};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use crate::backend::{create_backend, BackendKind};
+use crate::preset::Preset;
+use crate::{render_supersampled, FieldInputs};
+
+/// Default tile edge, ~28MB of fields and colours per tile
+pub static DEFAULT_TILE_SIZE: usize = 1024;
+
+/// Renders `preset` at its full size in tiles of at most `tile_size` square, calling
+/// `progress(done, total)` after each tile
+pub fn render_tiled(
+    backend_kind: BackendKind,
+    preset: &Preset,
+    tile_size: usize,
+    fpath: impl AsRef<Path>,
+    mut progress: impl FnMut(usize, usize),
+) -> Result<(), String> {
+    let (h, w) = preset.size;
+    let tile_dims = (tile_size.min(h).max(1), tile_size.min(w).max(1));
+    let n_tiles = h.div_ceil(tile_dims.0) * w.div_ceil(tile_dims.1);
+
+    let mut backend = create_backend(backend_kind, tile_dims, Some(preset.custom_code.clone()))?;
+    // the export size may differ from the one the view was set up at
+    let mut sfparam = preset.params.sfparam.clone();
+    sfparam.fit_aspect(preset.size);
+    sfparam.sync_custom_function(&preset.custom_code);
+    let inputs = FieldInputs::new(&sfparam)?;
+
+    let mut writer = preset.create_png_writer(fpath.as_ref().with_extension("png"))?;
+    let mut stream = writer.stream_writer().map_err(|err| err.to_string())?;
+    let mut band = Array3::<u8>::zeros((tile_dims.0, w, 3));
+
+    let mut done = 0;
+    for i0 in (0..h).step_by(tile_dims.0) {
+        let rows = tile_dims.0.min(h - i0);
+        for j0 in (0..w).step_by(tile_dims.1) {
+            let cols = tile_dims.1.min(w - j0);
+            // edge tiles are rendered at full tile size over the view's edge and cropped
+            let tile_inputs = inputs.tile(preset.size, (i0, j0), tile_dims);
+            render_supersampled(
+                backend.as_mut(),
+                &preset.params.vis_type,
+                &tile_inputs,
+                preset.params.supersampling,
+                &|| false,
+            )?;
+            band.slice_mut(s![..rows, j0..j0 + cols, ..])
+                .assign(&backend.rgb().slice(s![..rows, ..cols, ..]));
+            done += 1;
+            progress(done, n_tiles);
+        }
+        stream
+            .write_all(&band.as_slice().unwrap()[..rows * w * 3])
+            .map_err(|err| err.to_string())?;
+    }
+    stream.finish().map_err(|err| err.to_string())
+}
+
+/// GUI state for tiled exports, which run on their own thread and backend
+pub struct TiledExport {
+    pub size: (usize, usize),
+    pub tile_size: usize,
+    /// tiles (done, total)
+    progress: Arc<(AtomicUsize, AtomicUsize)>,
+    join_handle: Option<JoinHandle<Result<(), String>>>,
+}
+
+impl TiledExport {
+    pub fn new(size: (usize, usize)) -> Self {
+        Self {
+            size,
+            tile_size: DEFAULT_TILE_SIZE,
+            progress: Default::default(),
+            join_handle: None,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.join_handle.is_some()
+    }
+
+    fn start(&mut self, backend_kind: BackendKind, preset: Preset, fpath: PathBuf) {
+        let progress = self.progress.clone();
+        progress.0.store(0, Ordering::Relaxed);
+        progress.1.store(0, Ordering::Relaxed);
+        let tile_size = self.tile_size;
+        self.join_handle = Some(std::thread::spawn(move || {
+            render_tiled(backend_kind, &preset, tile_size, fpath, |done, total| {
+                progress.0.store(done, Ordering::Relaxed);
+                progress.1.store(total, Ordering::Relaxed);
+            })
+        }));
+    }
+
+    fn poll(&mut self) {
+        if let Some(handle) = self.join_handle.take() {
+            if !handle.is_finished() {
+                self.join_handle = Some(handle);
+                return;
+            }
+            match handle.join().expect("thread join error") {
+                Ok(_) => info!("Tiled export finished"),
+                Err(err) => error!("Tiled export failed: {err}"),
+            }
+        }
+    }
+
+    /// Settings and progress, `preset` being the current view to export at `size`
+    pub fn show(&mut self, ui: &mut egui::Ui, backend_kind: BackendKind, preset: Preset) {
+        self.poll();
+
+        ui.horizontal(|ui| {
+            ui.label("Export size: ");
+            ui.add(DragValue::new(&mut self.size.0));
+            ui.add(DragValue::new(&mut self.size.1));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Tile size: ");
+            ui.add(DragValue::new(&mut self.tile_size).range(64..=16384));
+        });
+
+        if self.is_running() {
+            let done = self.progress.0.load(Ordering::Relaxed);
+            let total = self.progress.1.load(Ordering::Relaxed).max(1);
+            ui.add(
+                egui::ProgressBar::new(done as f32 / total as f32)
+                    .text(format!("tile {done}/{total}")),
+            );
+            // keep the progress bar moving
+            ui.ctx().request_repaint();
+        } else if ui.button("Export png").clicked() {
+            if let Some(fpath) = rfd::FileDialog::new()
+                .set_directory(".")
+                .add_filter("PNG image", &["png"])
+                .save_file()
+            {
+                let preset = Preset {
+                    size: self.size,
+                    ..preset
+                };
+                self.start(backend_kind, preset, fpath);
+            }
+        }
+    }
+}
@@ -1,8 +1,10 @@
 use egui_inspect::{EguiInspect, InspectNumber};
 use ocl::OclPrm;
+use serde::{Deserialize, Serialize};
+use std::ops::{Add, Div, Mul, Sub};
 
 #[repr(C)]
-#[derive(EguiInspect, Clone, Copy, Debug, Default, PartialEq)]
+#[derive(EguiInspect, Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct BBox {
     #[inspect(min=-2.0, max=2.0)]
     pub left: f64,
@@ -16,8 +18,42 @@ pub struct BBox {
 
 unsafe impl OclPrm for BBox {}
 
+impl BBox {
+    /// The part of the view covered by the block of pixels at `offset` of size `dims`, in an
+    /// image of size `full_dims` spanning the whole view (same pixel mapping as the kernels)
+    pub fn sub_rect(
+        &self,
+        full_dims: (usize, usize),
+        offset: (usize, usize),
+        dims: (usize, usize),
+    ) -> BBox {
+        let di = (self.top - self.bot) / full_dims.0 as f64;
+        let dj = (self.right - self.left) / full_dims.1 as f64;
+        let bot = self.bot + offset.0 as f64 * di;
+        let left = self.left + offset.1 as f64 * dj;
+        BBox {
+            left,
+            right: left + dims.1 as f64 * dj,
+            bot,
+            top: bot + dims.0 as f64 * di,
+        }
+    }
+
+    /// The view moved by a fraction of a pixel, for an image of size `dims`
+    pub fn shifted(&self, dims: (usize, usize), offset: (f64, f64)) -> BBox {
+        let di = offset.0 * (self.top - self.bot) / dims.0 as f64;
+        let dj = offset.1 * (self.right - self.left) / dims.1 as f64;
+        BBox {
+            left: self.left + dj,
+            right: self.right + dj,
+            bot: self.bot + di,
+            top: self.top + di,
+        }
+    }
+}
+
 #[repr(C)]
-#[derive(Debug, EguiInspect, PartialEq, Clone, Copy)]
+#[derive(Debug, EguiInspect, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub struct Complex {
     #[inspect(min=-2.0, max=2.0)]
     pub re: f64,
@@ -31,6 +67,94 @@ impl Default for Complex {
     }
 }
 
+impl Complex {
+    pub fn norm_sqr(&self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, rhs: Complex) -> Complex {
+        Complex {
+            re: self.re + rhs.re,
+            im: self.im + rhs.im,
+        }
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex {
+            re: self.re - rhs.re,
+            im: self.im - rhs.im,
+        }
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex {
+            re: self.re * rhs.re - self.im * rhs.im,
+            im: self.im * rhs.re + self.re * rhs.im,
+        }
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+
+    fn div(self, rhs: Complex) -> Complex {
+        let d = rhs.norm_sqr();
+        Complex {
+            re: (self.re * rhs.re + self.im * rhs.im) / d,
+            im: (self.im * rhs.re - self.re * rhs.im) / d,
+        }
+    }
+}
+
+/// Built in formula selection, see `_iterate` in mandelutils.c
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FormulaParams {
+    /// 0 custom f, 1 mandelbrot, 2 burning ship, 3 tricorn, 4 multibrot, 5 celtic, 6 buffalo
+    pub id: i32,
+    pub power: f64,
+    pub flip: i32,
+}
+
+unsafe impl OclPrm for FormulaParams {}
+
+/// Early exits for interior points, see `_escape_iter` in mandelutils.c
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct InteriorChecks {
+    /// main cardioid and period 2 bulb, only valid for z^2+c in mandel mode
+    pub bulbs: i32,
+    /// Brent cycle detection
+    pub periodicity: i32,
+    /// distance in pixels at which an orbit point counts as revisited, see `_plane_interior`
+    pub tolerance: f64,
+}
+
+unsafe impl OclPrm for InteriorChecks {}
+
+/// Escape test of the orbits, see `in_bounds` in mandelutils.c
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Bailout {
+    pub radius: f64,
+    /// 0 euclidean, 1 square, 2 real, 3 manhattan, 4 custom `bailout(z)`
+    pub norm: i32,
+}
+
+unsafe impl OclPrm for Bailout {}
+
 /// Shared fractal params
 #[repr(C)]
 #[derive(Debug, Default, Clone, Copy, PartialEq)]
@@ -39,12 +163,15 @@ pub struct SFParam {
     pub c: Complex,
     pub view: BBox,
     pub max_iter: i32,
+    pub formula: FormulaParams,
+    pub interior: InteriorChecks,
+    pub bailout: Bailout,
 }
 
 unsafe impl OclPrm for SFParam {}
 
 #[repr(C)]
-#[derive(Debug, EguiInspect, PartialEq, Clone, Copy)]
+#[derive(Debug, EguiInspect, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub struct Freqs {
     #[inspect(log_slider, min = 1.0, max = 1000.0)]
     pub r: f64,
@@ -67,7 +194,7 @@ impl Default for Freqs {
 unsafe impl OclPrm for Freqs {}
 
 #[repr(C)]
-#[derive(Debug, EguiInspect, PartialEq, Clone, Copy)]
+#[derive(Debug, EguiInspect, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub struct ProxType {
     pub to_unit_circ: bool,
     pub to_horizontal: bool,
@@ -94,3 +221,241 @@ pub struct ImDims {
 }
 
 unsafe impl OclPrm for ImDims {}
+
+/// Parameters of the normalised (continuous) iteration count
+#[repr(C)]
+#[derive(Debug, EguiInspect, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct SmoothParams {
+    #[inspect(log_slider, min = 2.0, max = 1e6)]
+    pub bailout: f64,
+    /// growth exponent of the iteration function, 2 for the built in z^2+c
+    #[inspect(min = 1.01, max = 10.0)]
+    pub degree: f64,
+    /// estimate degree from the last two orbit points instead (for custom functions)
+    pub estimate_degree: bool,
+}
+
+impl Default for SmoothParams {
+    fn default() -> Self {
+        Self {
+            bailout: 1000.0,
+            degree: 2.0,
+            estimate_degree: false,
+        }
+    }
+}
+
+unsafe impl OclPrm for SmoothParams {}
+
+/// Parameters of the exterior distance estimate
+#[repr(C)]
+#[derive(Debug, EguiInspect, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct DistanceParams {
+    /// a large bailout keeps the estimate accurate
+    #[inspect(log_slider, min = 2.0, max = 1e6)]
+    pub bailout: f64,
+    /// distance in pixels at which the field saturates to 1
+    #[inspect(log_slider, min = 0.1, max = 100.0)]
+    pub thickness: f64,
+}
+
+impl Default for DistanceParams {
+    fn default() -> Self {
+        Self {
+            bailout: 1000.0,
+            thickness: 2.0,
+        }
+    }
+}
+
+unsafe impl OclPrm for DistanceParams {}
+
+/// How field values are placed on a palette lookup table, see `Palette::mapping`
+#[repr(C)]
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct PaletteMapping {
+    pub scale: f64,
+    pub offset: f64,
+    /// 0 clamp, 1 repeat, 2 mirror
+    pub repeat_mode: i32,
+}
+
+unsafe impl OclPrm for PaletteMapping {}
+
+/// Upper bound on the degree of newton polynomials, fixes the size of `NewtonParam`
+pub const MAX_NEWTON_DEGREE: usize = 8;
+
+/// Polynomial and method settings of the newton fields, see `NewtonParamUI`
+#[repr(C)]
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct NewtonParam {
+    /// coefficient of z^k at k
+    pub coeffs: [Complex; MAX_NEWTON_DEGREE + 1],
+    pub roots: [Complex; MAX_NEWTON_DEGREE],
+    pub degree: i32,
+    pub relaxation: Complex,
+    /// adds c after each step
+    pub nova: i32,
+    /// starting point of nova in mandel mode
+    pub start: Complex,
+    pub tolerance: f64,
+}
+
+unsafe impl OclPrm for NewtonParam {}
+
+/// Histogram settings of a buddhabrot pass, see `BuddhabrotParamUI`
+#[repr(C)]
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct BuddhabrotParam {
+    /// per histogram
+    pub max_iter: [i32; 3],
+    /// splat the orbits that stay bounded instead
+    pub anti: i32,
+    /// per pixel and pass
+    pub samples: i32,
+}
+
+unsafe impl OclPrm for BuddhabrotParam {}
+
+/// Logistic map settings of the lyapunov field, see `LyapunovParamUI`
+#[repr(C)]
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct LyapunovParam {
+    /// bit k set when the k-th rate of the sequence is b
+    pub sequence: u32,
+    pub length: i32,
+    pub warmup: i32,
+    pub contrast: f64,
+}
+
+unsafe impl OclPrm for LyapunovParam {}
+
+#[repr(C)]
+#[derive(EguiInspect, Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Vec3 {
+    #[inspect(min=-4.0, max=4.0)]
+    pub x: f64,
+    #[inspect(min=-4.0, max=4.0)]
+    pub y: f64,
+    #[inspect(min=-4.0, max=4.0)]
+    pub z: f64,
+}
+
+impl Vec3 {
+    pub fn new(x: f64, y: f64, z: f64) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn dot(self, other: Self) -> f64 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(self, other: Self) -> Self {
+        Self::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    pub fn length(self) -> f64 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalized(self) -> Self {
+        self * (1.0 / self.length())
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+impl Mul<f64> for Vec3 {
+    type Output = Self;
+
+    fn mul(self, rhs: f64) -> Self {
+        Self::new(self.x * rhs, self.y * rhs, self.z * rhs)
+    }
+}
+
+unsafe impl OclPrm for Vec3 {}
+
+/// Camera, shape and shading of the ray marched scene, see `SceneParamUI`
+#[repr(C)]
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct SceneParam {
+    /// full view, whose width the film spans
+    pub frame: BBox,
+    pub eye: Vec3,
+    /// orthonormal camera basis
+    pub forward: Vec3,
+    pub right: Vec3,
+    pub up: Vec3,
+    /// tan of half the horizontal field of view
+    pub fov_scale: f64,
+    /// 0 mandelbulb, 1 mandelbox
+    pub shape: i32,
+    pub power: f64,
+    pub box_scale: f64,
+    pub min_radius: f64,
+    pub fold_limit: f64,
+    pub iters: i32,
+    pub max_steps: i32,
+    /// hit distance per unit of distance travelled
+    pub detail: f64,
+    pub max_dist: f64,
+    /// towards the light, unit length
+    pub light_dir: Vec3,
+    pub softness: f64,
+    pub ao: f64,
+    pub specular: f64,
+    pub shininess: f64,
+    pub trap: ProxType,
+    pub trap_freqs: Freqs,
+}
+
+unsafe impl OclPrm for SceneParam {}
+
+/// Upper bound on the shapes composed by a shape trap, fixes the size of `ShapeTrapParam`
+pub const MAX_TRAP_SHAPES: usize = 8;
+
+/// A shape in its own frame, moved by the transform, see `_shape_distance` in mandelutils.c
+#[repr(C)]
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct TrapShape {
+    /// 0 point, 1 unit circle, 2 segment from -1 to 1, 3 cross of unit arms, 4 regular polygon of
+    /// circumradius 1, 5 custom `trap_sdf`
+    pub kind: i32,
+    pub sides: i32,
+    pub offset: Complex,
+    /// radians
+    pub angle: f64,
+    pub scale: f64,
+}
+
+/// Union of shapes the orbits are measured against, see `ShapeTrapUI`
+#[repr(C)]
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct ShapeTrapParam {
+    pub shapes: [TrapShape; MAX_TRAP_SHAPES],
+    pub n_shapes: i32,
+    /// 0 min distance, 1 average distance, 2 first hit
+    pub mode: i32,
+    /// distance counting as a hit
+    pub threshold: f64,
+}
+
+unsafe impl OclPrm for ShapeTrapParam {}